@@ -0,0 +1,236 @@
+use crate::crawler::{Crawler, Hash};
+use clap::ArgEnum;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Site {
+    Danbooru,
+    Gelbooru,
+    Yandere,
+    Konachan,
+}
+
+impl Site {
+    fn name(self) -> &'static str {
+        match self {
+            Site::Danbooru => "Danbooru",
+            Site::Gelbooru => "Gelbooru",
+            Site::Yandere => "yande.re",
+            Site::Konachan => "Konachan",
+        }
+    }
+}
+
+pub struct Credential {
+    pub user: String,
+    pub key: String,
+}
+
+struct Post {
+    id: u64,
+    md5: String,
+    url: String,
+    ext: String,
+    tags: String,
+    rating: String,
+    source: String,
+}
+
+pub fn crawl_posts(
+    crawler: &Crawler,
+    output: PathBuf,
+    site: Site,
+    credential: Option<Credential>,
+    tags: String,
+    pages: usize,
+    limit: usize,
+) {
+    // Crawl post lists page by page, saving images of each page before the next
+    // one so that large queries are not held in memory.
+    let directory = sanitize_filename::sanitize(format!("[{}] {tags}", site.name()));
+    let directory_path = output.join(&directory);
+    let mut posts = Vec::new();
+    for page in 1..=pages {
+        let (url, mut queries) = match site {
+            Site::Danbooru => (
+                "https://danbooru.donmai.us/posts.json",
+                vec![("page", page.to_string())],
+            ),
+            Site::Gelbooru => (
+                "https://gelbooru.com/index.php",
+                vec![
+                    ("page", String::from("dapi")),
+                    ("s", String::from("post")),
+                    ("q", String::from("index")),
+                    ("json", String::from("1")),
+                    ("pid", (page - 1).to_string()),
+                ],
+            ),
+            Site::Yandere => (
+                "https://yande.re/post.json",
+                vec![("page", page.to_string())],
+            ),
+            Site::Konachan => (
+                "https://konachan.com/post.json",
+                vec![("page", page.to_string())],
+            ),
+        };
+        queries.push(("tags", tags.clone()));
+        queries.push(("limit", limit.to_string()));
+        if let Some(credential) = &credential {
+            let (user, key) = match site {
+                Site::Danbooru => ("login", "api_key"),
+                Site::Gelbooru => ("user_id", "api_key"),
+                Site::Yandere | Site::Konachan => ("login", "password_hash"),
+            };
+            queries.push((user, credential.user.clone()));
+            queries.push((key, credential.key.clone()));
+        }
+        let queries = queries.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let list_result = crawler.get_json("", vec![(url, queries)]).pop().unwrap();
+        let list = match list_result {
+            Ok(list) => list,
+            Err(err) => {
+                println!("Fail to crawl post list {page} for \"{tags}\": {err}");
+                break;
+            }
+        };
+        let page_posts = extract_posts(site, &list);
+        if page_posts.is_empty() {
+            break;
+        }
+        if posts.is_empty() {
+            fs::create_dir(&directory_path).unwrap();
+        }
+        println!("{directory} - Page {page}, {} Posts", page_posts.len());
+
+        // Crawl images of posts, which are verified by their MD5 hashes.
+        let image_requests = page_posts
+            .iter()
+            .map(|post| {
+                (
+                    post.url.as_str(),
+                    Vec::new(),
+                    Some(Hash::Md5(post.md5.clone())),
+                )
+            })
+            .collect();
+        let image_results = crawler.get_image(&directory, image_requests);
+
+        // Write images to local files.
+        for (post, result) in page_posts.iter().zip(image_results) {
+            let id = post.id;
+            let image = match result {
+                Ok(image) => image,
+                Err(err) => {
+                    println!("Fail to crawl Post {id}: {err}");
+                    continue;
+                }
+            };
+            let mut path = directory_path.clone();
+            path.push(format!("{id}_{}{}", post.md5, post.ext));
+            let mut file = File::create(path).unwrap();
+            file.write_all(&image).unwrap();
+        }
+        posts.extend(page_posts);
+
+        // Save metadata of posts crawled so far.
+        let metadata: Vec<_> = posts
+            .iter()
+            .map(|post| {
+                json!({
+                    "id": post.id,
+                    "file": format!("{}_{}{}", post.id, post.md5, post.ext),
+                    "md5": post.md5,
+                    "url": post.url,
+                    "tags": post.tags,
+                    "rating": post.rating,
+                    "source": post.source,
+                })
+            })
+            .collect();
+        let file = File::create(directory_path.join("metadata.json")).unwrap();
+        serde_json::to_writer_pretty(file, &metadata).unwrap();
+    }
+}
+
+fn extract_posts(site: Site, list: &Value) -> Vec<Post> {
+    // Gelbooru wraps posts in an object, while others return them directly.
+    let posts = match site {
+        Site::Gelbooru => list["post"].as_array(),
+        _ => list.as_array(),
+    };
+    posts
+        .into_iter()
+        .flatten()
+        .filter_map(|post| {
+            // Posts without file urls are restricted and skipped.
+            let url = post["file_url"].as_str()?.to_string();
+            let ext = match post["file_ext"].as_str() {
+                Some(ext) => format!(".{ext}"),
+                None => url[url.rfind('.')?..].to_string(),
+            };
+            let tags = match site {
+                Site::Danbooru => &post["tag_string"],
+                _ => &post["tags"],
+            };
+            Some(Post {
+                id: post["id"].as_u64()?,
+                md5: post["md5"].as_str()?.to_string(),
+                url,
+                ext,
+                tags: tags.as_str().unwrap_or_default().to_string(),
+                rating: post["rating"].as_str().unwrap_or_default().to_string(),
+                source: post["source"].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_posts, Site};
+    use serde_json::json;
+
+    #[test]
+    fn danbooru_posts() {
+        let list = json!([
+            {
+                "id": 1,
+                "md5": "0123456789abcdef0123456789abcdef",
+                "file_ext": "png",
+                "file_url": "https://cdn.donmai.us/original/01/23/0123456789abcdef0123456789abcdef.png",
+                "tag_string": "1girl solo",
+                "rating": "g",
+                "source": "https://www.pixiv.net/artworks/1",
+            },
+            {"id": 2, "md5": "fedcba9876543210fedcba9876543210", "file_ext": "jpg"},
+        ]);
+        let posts = extract_posts(Site::Danbooru, &list);
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].ext, ".png");
+        assert_eq!(posts[0].tags, "1girl solo");
+    }
+
+    #[test]
+    fn gelbooru_posts() {
+        let list = json!({
+            "@attributes": {"limit": 100, "offset": 0, "count": 1},
+            "post": [{
+                "id": 3,
+                "md5": "0123456789abcdef0123456789abcdef",
+                "file_url": "https://img3.gelbooru.com/images/01/23/0123456789abcdef0123456789abcdef.jpg",
+                "tags": "1girl solo",
+                "rating": "general",
+                "source": "",
+            }],
+        });
+        let posts = extract_posts(Site::Gelbooru, &list);
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].id, 3);
+        assert_eq!(posts[0].ext, ".jpg");
+    }
+}
@@ -1,14 +1,19 @@
 use log::{debug, info};
+use md5::Md5;
 use reqwest::blocking::{Client, Request};
-use reqwest::header::{HeaderMap, HeaderName};
+use reqwest::header::{HeaderMap, HeaderName, CONTENT_TYPE};
 use serde_json::Value;
+use sha1::{Digest, Sha1};
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::File;
 use std::io::{self, Write};
 use std::mem;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+const FILE_TIMEOUT: u64 = 3600;
 const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15";
 
 struct Progress {
@@ -54,10 +59,34 @@ impl Progress {
     }
 }
 
+pub enum Hash {
+    Md5(String),
+    Sha1(String),
+}
+
+impl Hash {
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), String> {
+        // Sites may only provide a prefix of the hash.
+        let (name, expected, actual) = match self {
+            Hash::Md5(expected) => ("MD5", expected, format!("{:x}", Md5::digest(bytes))),
+            Hash::Sha1(expected) => ("SHA-1", expected, format!("{:x}", Sha1::digest(bytes))),
+        };
+        if actual.starts_with(&expected.to_lowercase()) {
+            Ok(())
+        } else {
+            Err(format!("{name} {actual} is not {expected}"))
+        }
+    }
+}
+
+pub type ImageRequest<'a> = (&'a str, Vec<(&'a str, &'a str)>, Option<Hash>);
+
 struct CrawlerRequest {
     id: usize,
     request: Request,
     retry: usize,
+    image: bool,
+    hash: Option<Hash>,
 }
 
 struct CrawlerResult {
@@ -115,16 +144,28 @@ impl Crawler {
 
                 // Check for waiting requests.
                 let request = requests.lock().unwrap().pop();
-                if let Some(CrawlerRequest { id, request, retry }) = request {
+                if let Some(CrawlerRequest {
+                    id,
+                    request,
+                    retry,
+                    image,
+                    hash,
+                }) = request
+                {
                     debug!("Request {id} - Start in Thread {c}: {request:?}");
 
-                    // Execute the request.
+                    // Execute the request, and verify the response body, which
+                    // may be truncated even if the status is successful.
                     let result = client.lock().unwrap().execute(request.try_clone().unwrap());
                     let result = match result {
-                        Ok(resp) if resp.status().is_success() => match resp.bytes() {
-                            Ok(bytes) => Ok(bytes.to_vec()),
-                            Err(err) => Err(err.to_string()),
-                        },
+                        Ok(resp) if resp.status().is_success() => {
+                            let length = resp.content_length();
+                            match resp.bytes() {
+                                Ok(bytes) => verify(&bytes, length, image, hash.as_ref())
+                                    .map(|_| bytes.to_vec()),
+                                Err(err) => Err(err.to_string()),
+                            }
+                        }
                         Ok(resp) => Err(resp.status().to_string()),
                         Err(err) => Err(err.to_string()),
                     };
@@ -154,6 +195,8 @@ impl Crawler {
                                     id,
                                     request,
                                     retry: retry - 1,
+                                    image,
+                                    hash,
                                 },
                             );
                         }
@@ -198,29 +241,156 @@ impl Crawler {
         name: &str,
         requests: Vec<(&str, Vec<(&str, &str)>)>,
     ) -> Vec<Result<Vec<u8>, String>> {
-        // Initialize the progress bar.
-        let total = requests.len();
-        let progress = Progress::new(name, total);
-        *self.progress.lock().unwrap() = progress;
-
-        // Build and submit requests.
         let requests = requests
             .iter()
-            .enumerate()
-            .map(|(id, (url, queries))| {
-                let request = self
-                    .client
+            .map(|(url, queries)| {
+                self.client
                     .lock()
                     .unwrap()
                     .get(*url)
                     .query(queries)
                     .build()
+                    .unwrap()
+            })
+            .collect();
+        self.execute(name, requests)
+    }
+
+    pub fn get_image(
+        &self,
+        name: &str,
+        requests: Vec<ImageRequest>,
+    ) -> Vec<Result<Vec<u8>, String>> {
+        let requests = requests
+            .into_iter()
+            .map(|(url, queries, hash)| {
+                let request = self
+                    .client
+                    .lock()
+                    .unwrap()
+                    .get(url)
+                    .query(&queries)
+                    .build()
                     .unwrap();
-                CrawlerRequest {
-                    id,
-                    request,
-                    retry: self.retry,
+                (request, true, hash)
+            })
+            .collect();
+        self.execute_verified(name, requests)
+    }
+
+    pub fn post_form(
+        &self,
+        name: &str,
+        requests: Vec<(&str, Vec<(&str, &str)>)>,
+    ) -> Vec<Result<String, String>> {
+        let requests = requests
+            .iter()
+            .map(|(url, form)| {
+                self.client
+                    .lock()
+                    .unwrap()
+                    .post(*url)
+                    .form(form)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        self.execute(name, requests)
+            .into_iter()
+            .map(|result| result.map(|bytes| String::from_utf8(bytes).unwrap()))
+            .collect()
+    }
+
+    pub fn post_json(
+        &self,
+        name: &str,
+        requests: Vec<(&str, Value)>,
+    ) -> Vec<Result<Value, String>> {
+        let requests = requests
+            .iter()
+            .map(|(url, json)| {
+                self.client
+                    .lock()
+                    .unwrap()
+                    .post(*url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(json.to_string())
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        self.execute(name, requests)
+            .into_iter()
+            .map(|result| result.map(|bytes| serde_json::from_slice(&bytes).unwrap()))
+            .collect()
+    }
+
+    pub fn get_file(&self, name: &str, url: &str, path: &Path) -> Result<u64, String> {
+        info!("Crawler Task \"{name}\" - Start (1 File)");
+        println!("{name} => Downloading");
+
+        // Stream the response body into the file, which may take much longer
+        // than the timeout for normal requests.
+        let client = self.client.lock().unwrap().clone();
+        let mut result = Err(String::new());
+        for _ in 0..=self.retry {
+            let response = client
+                .get(url)
+                .timeout(Duration::from_secs(FILE_TIMEOUT))
+                .send();
+            result = match response {
+                Ok(mut resp) if resp.status().is_success() => {
+                    let length = resp.content_length();
+                    let mut file = File::create(path).unwrap();
+                    match resp.copy_to(&mut file) {
+                        Ok(size) if length.is_some_and(|length| length != size) => Err(format!(
+                            "Truncated response ({size} of {} bytes)",
+                            length.unwrap()
+                        )),
+                        Ok(size) => Ok(size),
+                        Err(err) => Err(err.to_string()),
+                    }
                 }
+                Ok(resp) => Err(resp.status().to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            if result.is_ok() {
+                break;
+            }
+        }
+
+        info!("Crawler Task \"{name}\" - Complete");
+        result
+    }
+
+    fn execute(&self, name: &str, requests: Vec<Request>) -> Vec<Result<Vec<u8>, String>> {
+        let requests = requests
+            .into_iter()
+            .map(|request| (request, false, None))
+            .collect();
+        self.execute_verified(name, requests)
+    }
+
+    fn execute_verified(
+        &self,
+        name: &str,
+        requests: Vec<(Request, bool, Option<Hash>)>,
+    ) -> Vec<Result<Vec<u8>, String>> {
+        // Initialize the progress bar.
+        let total = requests.len();
+        let progress = Progress::new(name, total);
+        *self.progress.lock().unwrap() = progress;
+
+        // Submit requests.
+        let requests = requests
+            .into_iter()
+            .enumerate()
+            .map(|(id, (request, image, hash))| CrawlerRequest {
+                id,
+                request,
+                retry: self.retry,
+                image,
+                hash,
             })
             .collect();
         *self.requests.lock().unwrap() = requests;
@@ -244,9 +414,104 @@ impl Crawler {
     }
 }
 
+fn verify(
+    bytes: &[u8],
+    length: Option<u64>,
+    image: bool,
+    hash: Option<&Hash>,
+) -> Result<(), String> {
+    if let Some(length) = length {
+        if length != bytes.len() as u64 {
+            return Err(format!(
+                "Truncated response ({} of {length} bytes)",
+                bytes.len()
+            ));
+        }
+    }
+    if image {
+        verify_image(bytes)?;
+    }
+    if let Some(hash) = hash {
+        hash.verify(bytes)?;
+    }
+    Ok(())
+}
+
+pub fn name_directory(output: &Path, title: &str, id: &str) -> Option<String> {
+    // Name a gallery directory by its title, or by its id if the title is empty
+    // after sanitization. Another gallery with the same title, like a newer
+    // version, gets the id as a suffix.
+    let mut name = sanitize_filename::sanitize(title);
+    if name.is_empty() {
+        name = id.to_string();
+    }
+    if !output.join(&name).exists() {
+        return Some(name);
+    }
+    let name = format!("{name} ({id})");
+    if output.join(&name).exists() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+pub fn verify_image(bytes: &[u8]) -> Result<(), String> {
+    // Check end markers of known formats. Some encoders pad files with zeros.
+    let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    let complete = if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        bytes[..end].ends_with(&[0xFF, 0xD9])
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1A\n") {
+        bytes.len() >= 12 && &bytes[bytes.len() - 8..bytes.len() - 4] == b"IEND"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        bytes[..end].ends_with(&[0x3B])
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        let size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        bytes.len() >= size + 8
+    } else {
+        true
+    };
+    if complete {
+        Ok(())
+    } else {
+        Err(String::from("Truncated image"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Crawler, USER_AGENT};
+    use super::{verify, verify_image, Crawler, Hash, USER_AGENT};
+    use serde_json::json;
+
+    #[test]
+    fn truncated_images() {
+        assert!(verify_image(&[0xFF, 0xD8, 0xFF, 0xE0, 0xFF, 0xD9, 0x00]).is_ok());
+        assert!(verify_image(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00]).is_err());
+        let mut png = b"\x89PNG\r\n\x1A\n".to_vec();
+        png.extend(b"\0\0\0\0IEND\xAE\x42\x60\x82");
+        assert!(verify_image(&png).is_ok());
+        assert!(verify_image(&png[..png.len() - 4]).is_err());
+        assert!(verify_image(b"GIF89a\x3B").is_ok());
+        assert!(verify_image(b"GIF89a").is_err());
+        assert!(verify_image(b"RIFF\x04\0\0\0WEBP").is_ok());
+        assert!(verify_image(b"RIFF\x08\0\0\0WEBP").is_err());
+        assert!(verify_image(b"<html>").is_ok());
+    }
+
+    #[test]
+    fn hashes() {
+        let bytes = b"HTTPBIN is awesome";
+        assert!(verify(bytes, Some(18), false, None).is_ok());
+        assert!(verify(bytes, Some(20), false, None).is_err());
+        let md5 = Hash::Md5(String::from("d93bf0bc80a7de7e8968579fc7aafb8a"));
+        assert!(verify(bytes, None, false, Some(&md5)).is_ok());
+        let md5 = Hash::Md5(String::from("aeb4c2cb1d19a0e4ad6f2d1a5b5ecb0e"));
+        assert!(verify(bytes, None, false, Some(&md5)).is_err());
+        let sha1 = Hash::Sha1(String::from("29f2d3df3d"));
+        assert!(verify(bytes, None, false, Some(&sha1)).is_ok());
+        let sha1 = Hash::Sha1(String::from("0000000000"));
+        assert!(verify(bytes, None, false, Some(&sha1)).is_err());
+    }
 
     #[test]
     fn user_agent() {
@@ -302,6 +567,16 @@ mod tests {
         assert_eq!(value, "Sample Slide Show");
     }
 
+    #[test]
+    fn post_json() {
+        let crawler = Crawler::new(1, 60, Vec::new(), Vec::new(), 1);
+        let mut results =
+            crawler.post_json("", vec![("https://httpbin.org/post", json!({"K": "V"}))]);
+        let json = results.pop().unwrap().unwrap();
+        let value = json["json"]["K"].as_str().unwrap();
+        assert_eq!(value, "V");
+    }
+
     #[test]
     fn get_byte() {
         let crawler = Crawler::new(1, 60, Vec::new(), Vec::new(), 1);
@@ -0,0 +1,287 @@
+use clap::ArgEnum;
+use image::imageops::FilterType;
+use serde_derive::Deserialize;
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const INDEX: &str = ".h-crawler-index.json";
+const IMAGE_EXTS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+const MAX_THRESHOLD: u32 = 16;
+
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Skip,
+    Hardlink,
+    Report,
+}
+
+struct Entry {
+    path: String,
+    hash: u64,
+    digest: String,
+    len: u64,
+    modified: u64,
+}
+
+pub fn dedup_tree(output: &Path, mode: Mode, threshold: u32) {
+    if threshold > MAX_THRESHOLD {
+        println!("Dedup threshold should be at most {MAX_THRESHOLD}");
+        return;
+    }
+
+    // Load hashes of images seen in previous runs.
+    let index_path = output.join(INDEX);
+    let mut indexed: HashMap<_, _> = fs::read(&index_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .and_then(|index| index.as_object().map(extract_entries))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+
+    // Keep entries of unchanged images, and hash new or modified ones later.
+    let mut files = Vec::new();
+    collect_images(output, &mut files);
+    files.sort_unstable();
+    let mut entries = Vec::new();
+    let mut new_files = Vec::new();
+    for file in files {
+        let path = file
+            .strip_prefix(output)
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let (len, modified) = extract_stamp(&file);
+        match indexed.remove(&path) {
+            Some(entry) if entry.len == len && entry.modified == modified => entries.push(entry),
+            _ => new_files.push((file, path)),
+        }
+    }
+
+    // Index images by their digests for exact duplicates, and by parts of their
+    // perceptual hashes for near-duplicates, which share at least one part.
+    let mut digests = HashMap::new();
+    let mut parts: HashMap<_, Vec<_>> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        digests.entry(entry.digest.clone()).or_insert(i);
+        for part in split_hash(entry.hash, threshold) {
+            parts.entry(part).or_default().push(i);
+        }
+    }
+
+    // Find the earliest identical image, or the closest similar one within the
+    // threshold.
+    let mut duplicates = 0;
+    let mut similars = 0;
+    for (file, path) in new_files {
+        let bytes = fs::read(&file).unwrap();
+        let mut hash = match image::load_from_memory(&bytes) {
+            Ok(image) => hash_image(&image),
+            Err(err) => {
+                println!("Fail to hash {path}: {err}");
+                continue;
+            }
+        };
+        let mut digest = format!("{:x}", Sha1::digest(&bytes));
+        let original = match digests.get(&digest) {
+            Some(&i) => Some((i, true)),
+            None => split_hash(hash, threshold)
+                .flat_map(|part| parts.get(&part).into_iter().flatten())
+                .map(|&i| (i, (entries[i].hash ^ hash).count_ones()))
+                .filter(|(_, distance)| *distance <= threshold)
+                .min_by_key(|(i, distance)| (*distance, *i))
+                .map(|(i, _)| (i, false)),
+        };
+
+        // Pages of galleries and items of posts are checked against their
+        // metadata, so they are never removed, and only linked to identical
+        // images. Near-duplicates there, like blank pages, are only listed.
+        if let Some((i, identical)) = original {
+            let original = entries[i].path.clone();
+            let kind = if identical {
+                duplicates += 1;
+                "duplicate"
+            } else {
+                similars += 1;
+                "near-duplicate"
+            };
+            match mode {
+                Mode::Skip if !is_listed(&file) => {
+                    println!("{path} - Remove the {kind} of {original}");
+                    fs::remove_file(&file).unwrap();
+                    continue;
+                }
+                Mode::Hardlink if identical || !is_listed(&file) => {
+                    match link_file(&output.join(&original), &file) {
+                        Ok(()) => {
+                            println!("{path} - Link to the {kind} {original}");
+                            hash = entries[i].hash;
+                            digest = entries[i].digest.clone();
+                        }
+                        Err(err) => println!("Fail to link {path} to {original}: {err}"),
+                    }
+                }
+                _ if identical => println!("{path} - Duplicate of {original}"),
+                _ => println!("{path} - Similar to {original}"),
+            }
+        }
+
+        // Index this image.
+        let i = entries.len();
+        digests.entry(digest.clone()).or_insert(i);
+        for part in split_hash(hash, threshold) {
+            parts.entry(part).or_default().push(i);
+        }
+        let (len, modified) = extract_stamp(&file);
+        entries.push(Entry {
+            path,
+            hash,
+            digest,
+            len,
+            modified,
+        });
+    }
+    println!("{duplicates} Duplicates, {similars} Similar");
+
+    // Save the index.
+    let index: Map<_, _> = entries
+        .into_iter()
+        .map(|entry| {
+            let value = json!({
+                "hash": format!("{:016x}", entry.hash),
+                "digest": entry.digest,
+                "len": entry.len,
+                "modified": entry.modified,
+            });
+            (entry.path, value)
+        })
+        .collect();
+    let file = File::create(index_path).unwrap();
+    serde_json::to_writer(file, &index).unwrap();
+}
+
+fn collect_images(directory: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_images(&path, files);
+        } else if path
+            .extension()
+            .map(|ext| IMAGE_EXTS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+            .unwrap_or(false)
+        {
+            files.push(path);
+        }
+    }
+}
+
+fn is_listed(file: &Path) -> bool {
+    // Galleries and posts list their files in `metadata.json` or `post.json`,
+    // while a post with only one image is saved next to its metadata.
+    let directory = file.parent().unwrap();
+    directory.join("metadata.json").exists()
+        || directory.join("post.json").exists()
+        || file.with_extension("json").exists()
+}
+
+fn link_file(original: &Path, file: &Path) -> io::Result<()> {
+    // Link to a temporary path first, so that the file is kept if hard links
+    // are not supported.
+    let name = file.file_name().unwrap().to_string_lossy();
+    let temp = file.with_file_name(format!(".{name}.dedup"));
+    fs::hard_link(original, &temp)?;
+    fs::rename(&temp, file).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+fn extract_entries(index: &Map<String, Value>) -> Vec<Entry> {
+    index
+        .iter()
+        .filter_map(|(path, value)| {
+            Some(Entry {
+                path: path.clone(),
+                hash: u64::from_str_radix(value["hash"].as_str()?, 16).ok()?,
+                digest: value["digest"].as_str()?.to_string(),
+                len: value["len"].as_u64()?,
+                modified: value["modified"].as_u64()?,
+            })
+        })
+        .collect()
+}
+
+fn extract_stamp(file: &Path) -> (u64, u64) {
+    let meta = fs::metadata(file).unwrap();
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs());
+    (meta.len(), modified)
+}
+
+fn split_hash(hash: u64, threshold: u32) -> impl Iterator<Item = (u32, u64)> {
+    // Hashes within the threshold share at least one of `threshold + 1` parts.
+    let width = (64 + threshold) / (threshold + 1);
+    (0..=threshold).map(move |i| (i, (hash >> (i * width)) & (u64::MAX >> (64 - width))))
+}
+
+fn hash_image(image: &image::DynamicImage) -> u64 {
+    // The difference hash compares adjacent pixels of a 9x8 grayscale thumbnail,
+    // which survives resizing and recompression.
+    let thumbnail = image.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if thumbnail.get_pixel(x, y)[0] < thumbnail.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_image, split_hash};
+    use image::{DynamicImage, GrayImage, Luma};
+
+    const THRESHOLD: u32 = 4;
+
+    fn gradient(width: u32, height: u32, flip: bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            // Stripes and a gradient in relative coordinates, so that the pattern
+            // is kept when the size changes.
+            let x = if flip { width - 1 - x } else { x };
+            let stripe = (x * 6 / width + y * 4 / height) % 3 * 60;
+            Luma([(stripe + x * 60 / width) as u8])
+        }))
+    }
+
+    #[test]
+    fn near_duplicates() {
+        let hash = hash_image(&gradient(90, 80, false));
+        let resized = hash_image(&gradient(180, 160, false));
+        let flipped = hash_image(&gradient(90, 80, true));
+        assert!((hash ^ resized).count_ones() <= THRESHOLD);
+        assert!((hash ^ flipped).count_ones() > THRESHOLD);
+    }
+
+    #[test]
+    fn parts() {
+        let hash = 0x0123_4567_89ab_cdef;
+        let near = hash ^ 0x8000_0100_0020_0001;
+        let near_parts: Vec<_> = split_hash(near, THRESHOLD).collect();
+        assert!(split_hash(hash, THRESHOLD).any(|part| near_parts.contains(&part)));
+        assert_eq!(split_hash(hash, THRESHOLD).count(), THRESHOLD as usize + 1);
+        assert_eq!(split_hash(hash, 0).collect::<Vec<_>>(), vec![(0, hash)]);
+    }
+}
@@ -1,54 +1,444 @@
-use crate::crawler::Crawler;
+use crate::crawler::{name_directory, Crawler, Hash};
+use clap::ArgEnum;
 use kuchiki::traits::*;
 use kuchiki::{self, NodeRef};
 use lazy_static::lazy_static;
 use regex::Regex;
+use reqwest::Url;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const HOME_URL: &str = "https://e-hentai.org/home.php";
+const QUOTA_WAIT: u64 = 600;
+const IMAGE_EXTS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+
+const CATEGORIES: [&str; 10] = [
+    "misc",
+    "doujinshi",
+    "manga",
+    "artistcg",
+    "gamecg",
+    "imageset",
+    "cosplay",
+    "asianporn",
+    "non-h",
+    "western",
+];
+
+pub struct Search {
+    pub query: String,
+    pub categories: Vec<String>,
+    pub min_rating: Option<usize>,
+    pub pages: usize,
+}
+
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Archive {
+    Original,
+    Resample,
+}
+
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Torrent {
+    File,
+    Magnet,
+}
+
+#[derive(ArgEnum, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Domain {
+    #[serde(rename = "e-hentai")]
+    EHentai,
+    #[serde(rename = "exhentai")]
+    Exhentai,
+}
+
+impl Domain {
+    fn url(self) -> &'static str {
+        match self {
+            Domain::EHentai => "https://e-hentai.org",
+            Domain::Exhentai => "https://exhentai.org",
+        }
+    }
+}
+
+pub struct Options {
+    pub domain: Domain,
+    pub reload: usize,
+    pub original: bool,
+    pub quota_wait: bool,
+    pub archive: Option<Archive>,
+    pub extract: bool,
+    pub torrent: Option<Torrent>,
+    pub mpv: bool,
+    pub skip_expunged: bool,
+    pub exclude_tags: Vec<String>,
+    pub logged_in: bool,
+}
+
+struct Mpv {
+    gid: u64,
+    key: String,
+    api_url: String,
+    images: Vec<MpvImage>,
+}
+
+#[derive(Clone)]
+struct MpvImage {
+    page: usize,
+    key: String,
+    name: String,
+}
+
+struct TorrentInfo {
+    url: String,
+    name: String,
+    posted: String,
+    seeds: usize,
+}
 
 struct Image {
     page_url: String,
+    mpv: Option<MpvImage>,
+    token: Option<String>,
     reload_values: Vec<String>,
     image_url: String,
+    original_url: Option<String>,
+    ext: String,
     result: Result<Vec<u8>, String>,
 }
 
-pub fn crawl_galleries(crawler: &Crawler, output: PathBuf, reload: usize, galleries: Vec<String>) {
-    for gallery in galleries {
-        // Process the gallery id and the range.
-        let parts: Vec<_> = gallery.split('/').collect();
-        if parts.len() != 3 {
-            println!("Invalid Gallery {gallery}");
-            continue;
+impl Image {
+    fn new(page_url: String, mpv: Option<MpvImage>) -> Self {
+        // Page tokens are used to verify and reuse original images.
+        let token = match &mpv {
+            Some(mpv_image) => Some(mpv_image.key.clone()),
+            None => extract_page_token(&page_url),
+        };
+        Image {
+            page_url,
+            mpv,
+            token,
+            reload_values: Vec::new(),
+            image_url: String::new(),
+            original_url: None,
+            ext: String::new(),
+            result: Err(String::new()),
         }
-        let id = format!("{}/{}/", parts[0], parts[1]);
-        let range = parts[2];
-        let url = format!("https://exhentai.org/g/{id}");
+    }
+}
 
-        // Crawl gallery's home page.
-        let home_result = crawler
-            .get_text("", vec![(&url, Vec::new())])
-            .pop()
-            .unwrap();
+pub fn crawl_search(
+    crawler: &Crawler,
+    output: PathBuf,
+    options: &Options,
+    search: Search,
+    list: bool,
+) {
+    // Build search queries. `f_cats` is a bitmask of excluded categories.
+    let mut excluded = (1 << CATEGORIES.len()) - 1;
+    for category in &search.categories {
+        match CATEGORIES.iter().position(|c| c == category) {
+            Some(i) => excluded &= !(1 << i),
+            None => {
+                println!("Invalid Category {category}");
+                return;
+            }
+        }
+    }
+    let mut queries = vec![(String::from("f_search"), search.query.clone())];
+    if !search.categories.is_empty() {
+        queries.push((String::from("f_cats"), excluded.to_string()));
+    }
+    if let Some(min_rating) = search.min_rating {
+        queries.push((String::from("advsearch"), String::from("1")));
+        queries.push((String::from("f_sr"), String::from("on")));
+        queries.push((String::from("f_srdd"), min_rating.to_string()));
+    }
+
+    // Crawl search results.
+    let galleries = crawl_gallery_list(
+        crawler,
+        &format!("{}/", options.domain.url()),
+        queries,
+        search.pages,
+        &format!("Search \"{}\"", search.query),
+    );
+    println!(
+        "Search \"{}\" - {} Galleries",
+        search.query,
+        galleries.len()
+    );
+    if list {
+        for (gallery, title) in galleries {
+            println!("{gallery} {title}");
+        }
+    } else {
+        let galleries = galleries
+            .into_iter()
+            .map(|(gallery, _)| format!("{gallery}/"))
+            .collect();
+        crawl_galleries(crawler, output, options, galleries);
+    }
+}
+
+pub fn crawl_favorites(
+    crawler: &Crawler,
+    output: PathBuf,
+    options: &Options,
+    category: Option<usize>,
+    list: bool,
+) {
+    // Build favorites queries.
+    let mut queries = Vec::new();
+    match category {
+        Some(category) if category < 10 => {
+            queries.push((String::from("favcat"), category.to_string()));
+        }
+        Some(category) => {
+            println!("Invalid Favorite Category {category}");
+            return;
+        }
+        None => (),
+    }
+
+    // Crawl all favorites pages.
+    let galleries = crawl_gallery_list(
+        crawler,
+        &format!("{}/favorites.php", options.domain.url()),
+        queries,
+        usize::MAX,
+        "Favorites",
+    );
+    println!("Favorites - {} Galleries", galleries.len());
+    if list {
+        for (gallery, title) in galleries {
+            println!("{gallery} {title}");
+        }
+    } else {
+        let galleries = galleries
+            .into_iter()
+            .map(|(gallery, _)| format!("{gallery}/"))
+            .collect();
+        crawl_galleries(crawler, output, options, galleries);
+    }
+}
+
+fn crawl_gallery_list(
+    crawler: &Crawler,
+    url: &str,
+    queries: Vec<(String, String)>,
+    pages: usize,
+    name: &str,
+) -> Vec<(String, String)> {
+    let mut galleries = Vec::new();
+    let mut next: Option<String> = None;
+    for page in 1..=pages {
+        // Crawl a result page, following the `next` cursor.
+        let mut queries: Vec<_> = queries
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        if let Some(next) = &next {
+            queries.push(("next", next.as_str()));
+        }
+        let list_result = crawler.get_text("", vec![(url, queries)]).pop().unwrap();
+        let page = match list_result {
+            Ok(page) => page,
+            Err(err) => {
+                println!("Fail to crawl result page {page} for {name}: {err}");
+                break;
+            }
+        };
+
+        // Extract galleries and the next cursor.
+        let document = kuchiki::parse_html().one(page);
+        galleries.extend(extract_gallery_list(&document));
+        next = extract_next_cursor(&document);
+        if next.is_none() {
+            break;
+        }
+    }
+    galleries
+}
+
+pub fn update_galleries(crawler: &Crawler, output: PathBuf, options: &Options, reuse: bool) {
+    // Collect downloaded galleries from their metadata. Other sites also save
+    // metadata, which has no gid or token.
+    let mut downloaded = Vec::new();
+    for entry in fs::read_dir(&output).unwrap() {
+        let path = entry.unwrap().path();
+        let metadata: Value = match File::open(path.join("metadata.json")) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_default(),
+            Err(_) => continue,
+        };
+        if let (Some(gid), Some(token)) = (metadata["gid"].as_u64(), metadata["token"].as_str()) {
+            downloaded.push((path, gid, token.to_string()));
+        }
+    }
+    let downloaded_gids: HashSet<_> = downloaded.iter().map(|(_, gid, _)| *gid).collect();
+
+    // Crawl home pages of downloaded galleries.
+    let home_urls: Vec<_> = downloaded
+        .iter()
+        .map(|(_, gid, token)| format!("{}/g/{gid}/{token}/", options.domain.url()))
+        .collect();
+    let home_requests = home_urls
+        .iter()
+        .map(|url| (url.as_str(), Vec::new()))
+        .collect();
+    let home_results = crawler.get_text("Gallery Pages", home_requests);
+
+    for ((path, gid, token), home_result) in downloaded.iter().zip(home_results) {
         let page = match home_result {
             Ok(page) => page,
             Err(err) => {
-                println!("Fail to crawl the home page for Gallery {id}: {err}");
+                println!("Fail to crawl the home page for Gallery {gid}/{token}/: {err}");
                 continue;
             }
         };
 
-        // Extract the gallery title and the image count.
+        // Download the newest version unless it has been downloaded.
         let document = kuchiki::parse_html().one(page);
-        let title = extract_title(&document);
-        let count = extract_count(&document);
+        let (newer_gid, newer_token) = match extract_newer_version(&document) {
+            Some(newer) => newer,
+            None => continue,
+        };
+        if downloaded_gids.contains(&newer_gid) {
+            continue;
+        }
+        println!(
+            "{} - Newer Version {newer_gid}/{newer_token}/",
+            path.display()
+        );
+        let reusable_images = if reuse {
+            hash_images(path)
+        } else {
+            HashMap::new()
+        };
+        crawl_galleries_reusing(
+            crawler,
+            output.clone(),
+            options,
+            vec![format!("{newer_gid}/{newer_token}/")],
+            &reusable_images,
+        );
+    }
+}
 
-        // Create the gallery directory.
-        let mut directory_path = output.clone();
-        let directory = sanitize_filename::sanitize(&title);
-        directory_path.push(&directory);
-        fs::create_dir(&directory_path).unwrap();
+fn hash_images(directory_path: &Path) -> HashMap<String, PathBuf> {
+    // Image page tokens are the first 10 digits of SHA-1 hashes of original images.
+    let mut images = HashMap::new();
+    for entry in fs::read_dir(directory_path).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() && path.file_name().unwrap() != "metadata.json" {
+            let hash = format!("{:x}", Sha1::digest(fs::read(&path).unwrap()));
+            images.insert(hash[..10].to_string(), path);
+        }
+    }
+    images
+}
+
+pub fn crawl_galleries(
+    crawler: &Crawler,
+    output: PathBuf,
+    options: &Options,
+    galleries: Vec<String>,
+) {
+    crawl_galleries_reusing(crawler, output, options, galleries, &HashMap::new());
+}
+
+fn crawl_galleries_reusing(
+    crawler: &Crawler,
+    output: PathBuf,
+    options: &Options,
+    galleries: Vec<String>,
+    reusable_images: &HashMap<String, PathBuf>,
+) {
+    // Process gallery ids and ranges. Galleries given as urls are crawled from
+    // their own domains.
+    lazy_static! {
+        static ref GALLERY_REGEX: Regex =
+            Regex::new(r"^(?:https?://(e-hentai|exhentai)\.org/g/)?(\d+)/([0-9a-f]+)(?:/(.*))?$")
+                .unwrap();
+    }
+    let galleries: Vec<_> = galleries
+        .iter()
+        .filter_map(|gallery| match GALLERY_REGEX.captures(gallery) {
+            Some(caps) => {
+                // ExHentai pages are empty without cookies.
+                let domain = match caps.get(1).map(|domain| domain.as_str()) {
+                    Some("e-hentai") => Domain::EHentai,
+                    Some(_) if !options.logged_in => {
+                        println!("Crawl Gallery {gallery} from e-hentai.org without cookies");
+                        Domain::EHentai
+                    }
+                    Some(_) => Domain::Exhentai,
+                    None => options.domain,
+                };
+                let range = caps.get(4).map_or("", |range| range.as_str());
+                Some((
+                    domain,
+                    caps[2].to_string(),
+                    caps[3].to_string(),
+                    range.to_string(),
+                ))
+            }
+            None => {
+                println!("Invalid Gallery {gallery}");
+                None
+            }
+        })
+        .collect();
+
+    // Crawl gallery metadata through the API.
+    let gid_list: Vec<_> = galleries
+        .iter()
+        .map(|(_, gid, token, _)| (gid.as_str(), token.as_str()))
+        .collect();
+    let metadata_results = crawl_metadata(crawler, &gid_list);
+
+    for ((domain, gid, token, range), metadata_result) in galleries.iter().zip(metadata_results) {
+        let id = format!("{gid}/{token}/");
+        let url = format!("{}/g/{id}", domain.url());
+        let metadata = match metadata_result {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                println!("Fail to crawl the metadata for Gallery {id}: {err}");
+                continue;
+            }
+        };
+
+        // Filter galleries by their metadata.
+        if options.skip_expunged && metadata["expunged"].as_bool() == Some(true) {
+            println!("Skip expunged Gallery {id}");
+            continue;
+        }
+        let tags: Vec<_> = metadata["tags"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tag| tag.as_str().unwrap())
+            .collect();
+        if let Some(tag) = options.exclude_tags.iter().find(|exclude| {
+            tags.iter()
+                .any(|tag| tag == exclude || tag.rsplit(':').next() == Some(exclude.as_str()))
+        }) {
+            println!("Skip Gallery {id} tagged with {tag}");
+            continue;
+        }
+
+        // Extract the gallery title and the image count.
+        let title = extract_metadata_title(&metadata);
+        let count = metadata["filecount"].as_str().unwrap().parse().unwrap();
 
         // Determine a proper range.
         let (start, end) = if range.is_empty() {
@@ -56,53 +446,155 @@ pub fn crawl_galleries(crawler: &Crawler, output: PathBuf, reload: usize, galler
         } else {
             let range: Vec<_> = range.split('-').collect();
             if range.len() != 2 {
-                println!("Invalid range for Gallery {gallery}");
+                println!("Invalid range for Gallery {id}");
                 continue;
             }
             let start = range[0].parse().unwrap();
             let end = range[1].parse().unwrap();
             (start, end)
         };
-        let start_page = (start - 1) / 20;
-        let start = start - start_page * 20 - 1;
-        let end_page = (end - 1) / 20 + 1;
-        let end = end - start_page * 20;
-
-        // Crawl index pages.
-        let indexes: Vec<_> = (start_page..end_page).map(|i| i.to_string()).collect();
-        let index_requests = indexes
-            .iter()
-            .map(|i| (url.as_str(), vec![("p", i.as_str())]))
-            .collect();
-        let index_results = crawler.get_text("", index_requests);
-
-        // Extract links to image pages.
-        let mut image_page_urls = Vec::new();
-        for (index, index_result) in (start_page..end_page).zip(index_results) {
-            let pg = index + 1;
-            let page = match index_result {
-                Ok(page) => page,
+
+        // Name the gallery after its title.
+        let directory = match name_directory(&output, &title, gid) {
+            Some(directory) => directory,
+            None => {
+                println!("Skip Gallery {id}, which has been downloaded");
+                continue;
+            }
+        };
+
+        // Download the whole gallery as an archive if requested.
+        if let Some(archive) = options.archive {
+            if !range.is_empty() {
+                println!("Ignore the range for the archive of Gallery {id}");
+            }
+            if crawl_archive(
+                crawler, &output, options, *domain, archive, &metadata, &directory,
+            ) {
+                continue;
+            }
+            println!("Fall back to image pages for Gallery {id}");
+        }
+
+        // Save the best torrent instead if requested.
+        if let Some(torrent) = options.torrent {
+            let torrents_url = format!("{}/gallerytorrents.php?gid={gid}&t={token}", domain.url());
+            if crawl_torrent(crawler, &output, torrent, &torrents_url, &title, &directory) {
+                continue;
+            }
+            println!("Fall back to image pages for Gallery {id}");
+        }
+
+        // Make sure the image limit is enough for this gallery. Each image costs
+        // at least one point, so this is only a lower bound for original images.
+        if !check_image_limit(crawler, options, end - start + 1) {
+            println!("Abort before Gallery {id} to avoid exceeding the image limit");
+            return;
+        }
+
+        // Create the gallery directory.
+        let mut directory_path = output.clone();
+        directory_path.push(&directory);
+        fs::create_dir(&directory_path).unwrap();
+        write_metadata(&directory_path, &metadata, Some((start, end)));
+
+        // Initialize image tasks from the MPV if it is enabled for the account,
+        // which saves requests for index pages.
+        let mpv = if options.mpv {
+            let mpv_url = format!("{}/mpv/{id}", domain.url());
+            let mpv_result = crawler
+                .get_text("", vec![(&mpv_url, Vec::new())])
+                .pop()
+                .unwrap();
+            match mpv_result.map(|page| extract_mpv(&page, domain.url())) {
+                Ok(Some(mpv)) if mpv.images.len() < end => {
+                    println!("MPV does not cover the range of Gallery {id}");
+                    None
+                }
+                Ok(Some(mpv)) => Some(mpv),
+                Ok(None) => {
+                    println!("MPV is not available for Gallery {id}");
+                    None
+                }
                 Err(err) => {
-                    println!("Fail to crawl index page {pg} for Gallery {id}: {err}");
+                    println!("Fail to crawl the MPV for Gallery {id}: {err}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut images: Vec<_> = match &mpv {
+            Some(mpv) => mpv.images[start - 1..end]
+                .iter()
+                .map(|mpv_image| {
+                    let page_url = format!(
+                        "{}/s/{}/{}-{}",
+                        domain.url(),
+                        mpv_image.key,
+                        mpv.gid,
+                        mpv_image.page
+                    );
+                    Image::new(page_url, Some(mpv_image.clone()))
+                })
+                .collect(),
+            None => {
+                // Locate the range in index pages.
+                let start_page = (start - 1) / 20;
+                let start = start - start_page * 20 - 1;
+                let end_page = (end - 1) / 20 + 1;
+                let end = end - start_page * 20;
+
+                // Crawl index pages.
+                let indexes: Vec<_> = (start_page..end_page).map(|i| i.to_string()).collect();
+                let index_requests = indexes
+                    .iter()
+                    .map(|i| (url.as_str(), vec![("p", i.as_str())]))
+                    .collect();
+                let index_results = crawler.get_text("", index_requests);
+
+                // Extract links to image pages.
+                let mut image_page_urls = Vec::new();
+                for (index, index_result) in (start_page..end_page).zip(index_results) {
+                    let pg = index + 1;
+                    let page = match index_result {
+                        Ok(page) => page,
+                        Err(err) => {
+                            println!("Fail to crawl index page {pg} for Gallery {id}: {err}");
+                            continue;
+                        }
+                    };
+                    let document = kuchiki::parse_html().one(page);
+                    image_page_urls.extend(extract_image_page_urls(&document));
+                }
+                if image_page_urls.len() < end {
+                    println!("Fail to find image pages for Gallery {id}");
                     continue;
                 }
-            };
-            let document = kuchiki::parse_html().one(page);
-            image_page_urls.extend(extract_image_page_urls(&document));
-        }
-
-        // Initialize image tasks.
-        let mut images: Vec<_> = image_page_urls
-            .drain(start..end)
-            .map(|page_url| Image {
-                page_url,
-                reload_values: Vec::new(),
-                image_url: String::new(),
-                result: Err(String::new()),
-            })
-            .collect();
+                image_page_urls
+                    .drain(start..end)
+                    .map(|page_url| Image::new(page_url, None))
+                    .collect()
+            }
+        };
+
+        // Reuse identical images from a previous version.
+        for image in images.iter_mut() {
+            if let Some(path) = image
+                .token
+                .as_ref()
+                .and_then(|token| reusable_images.get(token))
+            {
+                image.ext = path
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                image.result = Ok(fs::read(path).unwrap());
+            }
+        }
 
         let mut batch = 0;
+        let mut limit_exceeded = false;
         while !images.is_empty() {
             // Crawl a huge gallery in batches.
             batch += 1;
@@ -113,8 +605,8 @@ pub fn crawl_galleries(crawler: &Crawler, output: PathBuf, reload: usize, galler
             };
             let mut images: Vec<_> = images.drain(..end).collect();
 
-            for r in 0..=reload {
-                // Crawl image pages.
+            for r in 0..=options.reload {
+                // Collect images to crawl.
                 let uncrawled_images: Vec<_> = images
                     .iter_mut()
                     .filter(|image| image.result.is_err())
@@ -122,51 +614,178 @@ pub fn crawl_galleries(crawler: &Crawler, output: PathBuf, reload: usize, galler
                 if uncrawled_images.is_empty() {
                     break;
                 }
-                let image_page_requests = uncrawled_images
-                    .iter()
-                    .map(|image| {
-                        (
-                            image.page_url.as_str(),
-                            image
-                                .reload_values
-                                .iter()
-                                .map(|v| ("nl", v.as_str()))
-                                .collect(),
-                        )
-                    })
-                    .collect();
-                let image_page_results = crawler.get_text(
-                    &format!("{title} (batch {batch}, page, reload {r})"),
-                    image_page_requests,
-                );
-
-                // Crawl images.
-                let uncrawled_images: Vec<_> = uncrawled_images
+                let (mpv_images, mut page_images): (Vec<_>, Vec<_>) = uncrawled_images
                     .into_iter()
-                    .zip(image_page_results)
-                    .filter_map(|(image, result)| match result {
-                        Ok(page) => {
-                            let document = kuchiki::parse_html().one(page);
-                            image.image_url = extract_image_url(&document);
-                            image.reload_values.push(extract_reload_value(&document));
-                            Some(image)
+                    .partition(|image| image.mpv.is_some());
+                let mut uncrawled_images = Vec::new();
+
+                // Dispatch image urls through the MPV API. Images fall back to
+                // their pages if the API returns an error, like an expired key.
+                if !mpv_images.is_empty() {
+                    let mpv = mpv.as_ref().unwrap();
+                    let dispatch_requests = mpv_images
+                        .iter()
+                        .map(|image| {
+                            let mpv_image = image.mpv.as_ref().unwrap();
+                            let mut json = json!({
+                                "method": "imagedispatch",
+                                "gid": mpv.gid,
+                                "page": mpv_image.page,
+                                "imgkey": mpv_image.key,
+                                "mpvkey": mpv.key,
+                            });
+                            if let Some(nl) = image.reload_values.last() {
+                                json["nl"] = json!(nl);
+                            }
+                            (mpv.api_url.as_str(), json)
+                        })
+                        .collect();
+                    let dispatch_results = crawler.post_json(
+                        &format!("{title} (batch {batch}, dispatch, reload {r})"),
+                        dispatch_requests,
+                    );
+                    for (image, result) in mpv_images.into_iter().zip(dispatch_results) {
+                        let json = match result {
+                            Ok(json) => json,
+                            Err(err) => {
+                                image.result = Err(err);
+                                continue;
+                            }
+                        };
+                        image.image_url = match json["i"].as_str() {
+                            Some(image_url) => image_url.to_string(),
+                            None => {
+                                let pg = image.mpv.take().unwrap().page;
+                                println!(
+                                    "Fail to dispatch page {pg} for Gallery {id}: {}",
+                                    json["error"]
+                                );
+                                page_images.push(image);
+                                continue;
+                            }
+                        };
+                        if is_limit_placeholder(&image.image_url) {
+                            limit_exceeded = true;
+                            image.result = Err(String::from("Image limit exceeded"));
+                            continue;
                         }
-                        Err(err) => {
-                            image.result = Err(err);
-                            None
+                        image.reload_values.push(match &json["s"] {
+                            Value::String(s) => s.clone(),
+                            s => s.to_string(),
+                        });
+                        image.original_url = match json["lf"].as_str() {
+                            Some(lf) if options.original => Some(format!("{}/{lf}", domain.url())),
+                            _ => None,
+                        };
+                        image.ext = match &image.original_url {
+                            Some(_) => extract_ext(&image.mpv.as_ref().unwrap().name),
+                            None => extract_ext(&image.image_url),
+                        };
+                        uncrawled_images.push(image);
+                    }
+                }
+
+                // Crawl image pages.
+                if !page_images.is_empty() {
+                    let image_page_requests = page_images
+                        .iter()
+                        .map(|image| {
+                            (
+                                image.page_url.as_str(),
+                                image
+                                    .reload_values
+                                    .iter()
+                                    .map(|v| ("nl", v.as_str()))
+                                    .collect(),
+                            )
+                        })
+                        .collect();
+                    let image_page_results = crawler.get_text(
+                        &format!("{title} (batch {batch}, page, reload {r})"),
+                        image_page_requests,
+                    );
+                    for (image, result) in page_images.into_iter().zip(image_page_results) {
+                        let page = match result {
+                            Ok(page) => page,
+                            Err(err) => {
+                                image.result = Err(err);
+                                continue;
+                            }
+                        };
+                        let document = kuchiki::parse_html().one(page);
+                        image.image_url = extract_image_url(&document);
+                        if is_limit_placeholder(&image.image_url) {
+                            limit_exceeded = true;
+                            image.result = Err(String::from("Image limit exceeded"));
+                            continue;
                         }
-                    })
-                    .collect();
+                        image.reload_values.push(extract_reload_value(&document));
+                        image.original_url = if options.original {
+                            extract_original_url(&document)
+                        } else {
+                            None
+                        };
+                        image.ext = image
+                            .original_url
+                            .as_ref()
+                            .and_then(|_| extract_original_ext(&document))
+                            .unwrap_or_else(|| extract_ext(&image.image_url));
+                        uncrawled_images.push(image);
+                    }
+                }
+
+                // Crawl images. Original images are verified by their page tokens.
                 let image_requests = uncrawled_images
                     .iter()
-                    .map(|image| (image.image_url.as_str(), Vec::new()))
+                    .map(|image| match &image.original_url {
+                        Some(url) => (
+                            url.as_str(),
+                            Vec::new(),
+                            image.token.clone().map(Hash::Sha1),
+                        ),
+                        None => (image.image_url.as_str(), Vec::new(), None),
+                    })
                     .collect();
-                let image_results = crawler.get_byte(
+                let image_results = crawler.get_image(
                     &format!("{title} (batch {batch}, image, reload {r})"),
                     image_requests,
                 );
+
+                // Fall back to resampled images if original images are unavailable,
+                // which happens when the image limit is exhausted.
+                let mut fallback_images = Vec::new();
                 for (image, result) in uncrawled_images.into_iter().zip(image_results) {
-                    image.result = result;
+                    let is_placeholder =
+                        matches!(&result, Ok(bytes) if is_placeholder_image(bytes, &image.ext));
+                    if image.original_url.is_some() && (result.is_err() || is_placeholder) {
+                        image.original_url = None;
+                        image.ext = extract_ext(&image.image_url);
+                        fallback_images.push(image);
+                    } else if is_placeholder {
+                        limit_exceeded = true;
+                        image.result = Err(String::from("Image limit exceeded"));
+                    } else {
+                        image.result = result;
+                    }
+                }
+                if !fallback_images.is_empty() {
+                    let fallback_requests = fallback_images
+                        .iter()
+                        .map(|image| (image.image_url.as_str(), Vec::new(), None))
+                        .collect();
+                    let fallback_results = crawler.get_image(
+                        &format!("{title} (batch {batch}, fallback, reload {r})"),
+                        fallback_requests,
+                    );
+                    for (image, result) in fallback_images.into_iter().zip(fallback_results) {
+                        match result {
+                            Ok(bytes) if is_placeholder_image(&bytes, &image.ext) => {
+                                limit_exceeded = true;
+                                image.result = Err(String::from("Image limit exceeded"));
+                            }
+                            result => image.result = result,
+                        }
+                    }
                 }
             }
 
@@ -175,41 +794,414 @@ pub fn crawl_galleries(crawler: &Crawler, output: PathBuf, reload: usize, galler
                 let pg = (batch - 1) * 100 + i + 1;
                 match &image.result {
                     Ok(img) => {
-                        let ext = {
-                            lazy_static! {
-                                static ref EXT_REGEX: Regex = Regex::new(r"\.[^\.]+$").unwrap();
-                            }
-                            let caps = EXT_REGEX.captures(&image.image_url).unwrap();
-                            caps[0].to_string()
-                        };
                         let mut path = directory_path.clone();
-                        path.push(format!("{pg:0>4}{ext}"));
+                        path.push(format!("{pg:0>4}{}", image.ext));
                         let mut file = File::create(path).unwrap();
                         file.write_all(img).unwrap();
                     }
                     Err(err) => println!("Fail to crawl page {pg} for Gallery {id}: {err}"),
                 }
             }
+
+            // Stop instead of saving placeholders once the image limit is exceeded.
+            if limit_exceeded {
+                println!("Image limit exceeded for Gallery {id}, check {HOME_URL} before resuming");
+                return;
+            }
+        }
+    }
+}
+
+fn crawl_archive(
+    crawler: &Crawler,
+    output: &Path,
+    options: &Options,
+    domain: Domain,
+    archive: Archive,
+    metadata: &Value,
+    directory: &str,
+) -> bool {
+    // Request an archive from the archiver, which costs GP.
+    let archiver_url = format!(
+        "{}/archiver.php?gid={}&token={}&or={}",
+        domain.url(),
+        metadata["gid"],
+        metadata["token"].as_str().unwrap(),
+        metadata["archiver_key"].as_str().unwrap(),
+    );
+    let form = match archive {
+        Archive::Original => vec![("dltype", "org"), ("dlcheck", "Download Original Archive")],
+        Archive::Resample => vec![("dltype", "res"), ("dlcheck", "Download Resample Archive")],
+    };
+    let archiver_result = crawler
+        .post_form("", vec![(&archiver_url, form)])
+        .pop()
+        .unwrap();
+    let download_url = match archiver_result.map(|page| extract_archive_url(&page)) {
+        Ok(Some(url)) => format!("{url}?start=1"),
+        Ok(None) => {
+            println!("Fail to request the archive for {directory}, which may need more GP");
+            return false;
+        }
+        Err(err) => {
+            println!("Fail to request the archive for {directory}: {err}");
+            return false;
+        }
+    };
+
+    // Stream the archive from H@H.
+    let archive_path = output.join(format!("{directory}.zip"));
+    if let Err(err) = crawler.get_file(directory, &download_url, &archive_path) {
+        println!("Fail to download the archive for {directory}: {err}");
+        let _ = fs::remove_file(&archive_path);
+        return false;
+    }
+
+    // Error pages may be served instead of the archive.
+    let mut zip = match zip::ZipArchive::new(File::open(&archive_path).unwrap()) {
+        Ok(zip) => zip,
+        Err(err) => {
+            println!("Fail to open the archive for {directory}: {err}");
+            fs::remove_file(&archive_path).unwrap();
+            return false;
+        }
+    };
+    if !options.extract {
+        return true;
+    }
+
+    // Extract images into the gallery directory, named by their orders in the
+    // archive, which follow the gallery rather than their names. Other entries
+    // like text notes are not pages.
+    let directory_path = output.join(directory);
+    fs::create_dir(&directory_path).unwrap();
+    write_metadata(&directory_path, metadata, None);
+    let mut pg = 0;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).unwrap();
+        let ext = match Path::new(entry.name()).extension() {
+            Some(ext) if IMAGE_EXTS.contains(&ext.to_string_lossy().to_lowercase().as_str()) => {
+                format!(".{}", ext.to_string_lossy())
+            }
+            _ => continue,
+        };
+        pg += 1;
+        let mut file = File::create(directory_path.join(format!("{pg:0>4}{ext}"))).unwrap();
+        io::copy(&mut entry, &mut file).unwrap();
+    }
+    fs::remove_file(&archive_path).unwrap();
+    true
+}
+
+fn crawl_torrent(
+    crawler: &Crawler,
+    output: &Path,
+    torrent: Torrent,
+    torrents_url: &str,
+    title: &str,
+    directory: &str,
+) -> bool {
+    // Pick the most seeded torrent, preferring newer ones.
+    let torrents_result = crawler
+        .get_text("", vec![(torrents_url, Vec::new())])
+        .pop()
+        .unwrap();
+    let torrents = match torrents_result {
+        Ok(page) => extract_torrents(&kuchiki::parse_html().one(page)),
+        Err(err) => {
+            println!("Fail to crawl torrents for {title}: {err}");
+            return false;
+        }
+    };
+    let best = match torrents
+        .into_iter()
+        .max_by(|a, b| (a.seeds, &a.posted).cmp(&(b.seeds, &b.posted)))
+    {
+        Some(best) => best,
+        None => {
+            println!("No torrent for {title}");
+            return false;
+        }
+    };
+
+    // Save the torrent file or its magnet link next to the gallery.
+    match torrent {
+        Torrent::File => {
+            let torrent_result = crawler
+                .get_byte("", vec![(&best.url, Vec::new())])
+                .pop()
+                .unwrap();
+            match torrent_result {
+                Ok(bytes) => {
+                    let mut file =
+                        File::create(output.join(format!("{directory}.torrent"))).unwrap();
+                    file.write_all(&bytes).unwrap();
+                }
+                Err(err) => {
+                    println!("Fail to download the torrent for {title}: {err}");
+                    return false;
+                }
+            }
+        }
+        Torrent::Magnet => {
+            let magnet = match build_magnet(&best) {
+                Some(magnet) => magnet,
+                None => {
+                    println!("Fail to find the info hash of the torrent for {title}");
+                    return false;
+                }
+            };
+            let mut file = File::create(output.join(format!("{directory}.magnet"))).unwrap();
+            writeln!(file, "{magnet}").unwrap();
+        }
+    }
+    println!("{title} - Torrent {} ({} Seeds)", best.name, best.seeds);
+    true
+}
+
+fn build_magnet(torrent: &TorrentInfo) -> Option<String> {
+    lazy_static! {
+        static ref HASH_REGEX: Regex = Regex::new(r"([0-9a-f]{40})\.torrent").unwrap();
+    }
+    let hash = &HASH_REGEX.captures(&torrent.url)?[1];
+    let dn = Url::parse_with_params("magnet:", &[("dn", &torrent.name)]).unwrap();
+    Some(format!("magnet:?xt=urn:btih:{hash}&{}", dn.query()?))
+}
+
+fn crawl_metadata(crawler: &Crawler, galleries: &[(&str, &str)]) -> Vec<Result<Value, String>> {
+    // The API accepts at most 25 galleries in each request.
+    let metadata_requests = galleries
+        .chunks(25)
+        .map(|chunk| {
+            let gid_list: Vec<_> = chunk
+                .iter()
+                .map(|(gid, token)| json!([gid.parse::<u64>().unwrap(), token]))
+                .collect();
+            let json = json!({
+                "method": "gdata",
+                "gidlist": gid_list,
+                "namespace": 1,
+            });
+            ("https://api.e-hentai.org/api.php", json)
+        })
+        .collect();
+    let metadata_results = crawler.post_json("", metadata_requests);
+
+    // Match metadata with galleries.
+    let mut metadata = Vec::new();
+    for (chunk, result) in galleries.chunks(25).zip(metadata_results) {
+        match result {
+            Ok(json) => {
+                let gmetadata = json["gmetadata"].as_array().unwrap();
+                metadata.extend(chunk.iter().map(|(gid, _)| {
+                    let gid: u64 = gid.parse().unwrap();
+                    let gallery = gmetadata
+                        .iter()
+                        .find(|gallery| gallery["gid"].as_u64() == Some(gid))
+                        .ok_or_else(|| String::from("Missing gallery"))?;
+                    match gallery["error"].as_str() {
+                        Some(err) => Err(err.to_string()),
+                        None => Ok(gallery.clone()),
+                    }
+                }));
+            }
+            Err(err) => metadata.extend(chunk.iter().map(|_| Err(err.clone()))),
+        }
+    }
+    metadata
+}
+
+fn write_metadata(directory_path: &Path, metadata: &Value, range: Option<(usize, usize)>) {
+    // Record the downloaded range, so that pages out of it are not missing.
+    let mut metadata = metadata.clone();
+    if let Some((start, end)) = range {
+        metadata["range"] = json!([start, end]);
+    }
+    let file = File::create(directory_path.join("metadata.json")).unwrap();
+    serde_json::to_writer_pretty(file, &metadata).unwrap();
+}
+
+fn check_image_limit(crawler: &Crawler, options: &Options, cost: usize) -> bool {
+    // The image limit is only shown to logged-in users.
+    if !options.logged_in {
+        return true;
+    }
+    loop {
+        let home_result = crawler
+            .get_text("", vec![(HOME_URL, Vec::new())])
+            .pop()
+            .unwrap();
+        let (current, limit) = match home_result.map(|page| extract_image_limit(&page)) {
+            Ok(Some(image_limit)) => image_limit,
+            Ok(None) => {
+                println!("Fail to find the image limit");
+                return true;
+            }
+            Err(err) => {
+                println!("Fail to crawl the image limit: {err}");
+                return true;
+            }
+        };
+        if current + cost <= limit {
+            return true;
+        }
+        println!("Image limit {current}/{limit} is not enough for {cost} images");
+        if !options.quota_wait {
+            return false;
         }
+        println!(
+            "Wait {} minutes for the image limit to recover",
+            QUOTA_WAIT / 60
+        );
+        thread::sleep(Duration::from_secs(QUOTA_WAIT));
+    }
+}
+
+fn extract_newer_version(document: &NodeRef) -> Option<(u64, String)> {
+    // Newer versions are listed from the oldest to the newest.
+    lazy_static! {
+        static ref GALLERY_REGEX: Regex = Regex::new(r"/g/(\d+)/([0-9a-f]+)").unwrap();
+    }
+    let href = document
+        .select("#gnd a")
+        .unwrap()
+        .filter_map(|a| a.attributes.borrow().get("href").map(String::from))
+        .last()?;
+    let caps = GALLERY_REGEX.captures(&href)?;
+    Some((caps[1].parse().ok()?, caps[2].to_string()))
+}
+
+fn extract_page_token(page_url: &str) -> Option<String> {
+    lazy_static! {
+        static ref TOKEN_REGEX: Regex = Regex::new(r"/s/([0-9a-f]{10})/").unwrap();
     }
+    TOKEN_REGEX
+        .captures(page_url)
+        .map(|caps| caps[1].to_string())
 }
 
-fn extract_title(document: &NodeRef) -> String {
-    let title = document.select_first("#gj").unwrap().text_contents();
-    if title.is_empty() {
-        document.select_first("#gn").unwrap().text_contents()
+fn extract_metadata_title(metadata: &Value) -> String {
+    // Prefer the Japanese title as the gallery page does. Titles from the API
+    // are HTML-escaped.
+    let title = metadata["title_jpn"].as_str().unwrap();
+    let title = if title.is_empty() {
+        metadata["title"].as_str().unwrap()
     } else {
         title
+    };
+    kuchiki::parse_html().one(title).text_contents()
+}
+
+fn extract_archive_url(page: &str) -> Option<String> {
+    lazy_static! {
+        static ref ARCHIVE_REGEX: Regex =
+            Regex::new(r#"(https?://[^"'\s]+/archive/[^"'\s?]+)"#).unwrap();
     }
+    ARCHIVE_REGEX.captures(page).map(|caps| caps[1].to_string())
 }
 
-fn extract_count(document: &NodeRef) -> usize {
-    let count = document.select_first(".gpc").unwrap().text_contents();
+fn extract_torrents(document: &NodeRef) -> Vec<TorrentInfo> {
     lazy_static! {
-        static ref COUNT_REGEX: Regex = Regex::new(r"([,\d]+)\s+images$").unwrap();
+        static ref POSTED_REGEX: Regex = Regex::new(r"Posted:\s*([\d-]+ [\d:]+)").unwrap();
+        static ref SEEDS_REGEX: Regex = Regex::new(r"Seeds:\s*(\d+)").unwrap();
     }
-    let caps = COUNT_REGEX.captures(&count).unwrap();
-    caps[1].replace(',', "").parse().unwrap()
+    document
+        .select("form")
+        .unwrap()
+        .filter_map(|form| {
+            let link = form.as_node().select_first("a[href*='.torrent']").ok()?;
+            let url = link.attributes.borrow().get("href")?.to_string();
+            let info = form.text_contents();
+            Some(TorrentInfo {
+                url,
+                name: link.text_contents().trim().to_string(),
+                posted: POSTED_REGEX.captures(&info)?[1].to_string(),
+                seeds: SEEDS_REGEX.captures(&info)?[1].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn extract_mpv(page: &str, base_url: &str) -> Option<Mpv> {
+    // The MPV page defines variables like `var imagelist = [{"n":"001.jpg","k":"abc"}];`.
+    lazy_static! {
+        static ref GID_REGEX: Regex = Regex::new(r"var gid\s*=\s*(\d+);").unwrap();
+        static ref KEY_REGEX: Regex = Regex::new(r#"var mpvkey\s*=\s*"([^"]+)";"#).unwrap();
+        static ref API_REGEX: Regex = Regex::new(r#"var api_url\s*=\s*"([^"]+)";"#).unwrap();
+        static ref LIST_REGEX: Regex = Regex::new(r"var imagelist\s*=\s*(\[.*?\]);").unwrap();
+    }
+    let gid = GID_REGEX.captures(page)?[1].parse().ok()?;
+    let key = KEY_REGEX.captures(page)?[1].to_string();
+    let api_url = API_REGEX
+        .captures(page)
+        .map(|caps| caps[1].to_string())
+        .unwrap_or_else(|| format!("{base_url}/api.php"));
+    let list: Value = serde_json::from_str(&LIST_REGEX.captures(page)?[1]).ok()?;
+    let images = list
+        .as_array()?
+        .iter()
+        .enumerate()
+        .map(|(i, image)| MpvImage {
+            page: i + 1,
+            key: image["k"].as_str().unwrap().to_string(),
+            name: image["n"].as_str().unwrap().to_string(),
+        })
+        .collect();
+    Some(Mpv {
+        gid,
+        key,
+        api_url,
+        images,
+    })
+}
+
+fn extract_image_limit(page: &str) -> Option<(usize, usize)> {
+    // The limit looks like `You are currently at 1,234 towards a limit of 5,000.`
+    let document = kuchiki::parse_html().one(page);
+    let text = document.text_contents();
+    lazy_static! {
+        static ref LIMIT_REGEX: Regex =
+            Regex::new(r"currently at\s+([,\d]+)\s+towards.*?limit of\s+([,\d]+)").unwrap();
+    }
+    let caps = LIMIT_REGEX.captures(&text)?;
+    let current = caps[1].replace(',', "").parse().ok()?;
+    let limit = caps[2].replace(',', "").parse().ok()?;
+    Some((current, limit))
+}
+
+fn is_limit_placeholder(url: &str) -> bool {
+    url.ends_with("/509.gif") || url.ends_with("/509s.gif")
+}
+
+fn is_placeholder_image(bytes: &[u8], ext: &str) -> bool {
+    // Images over the limit are redirected to the `509` GIF, and original images
+    // are replaced by an HTML page.
+    bytes.starts_with(b"<") || (bytes.starts_with(b"GIF") && ext != ".gif")
+}
+
+fn extract_gallery_list(document: &NodeRef) -> Vec<(String, String)> {
+    lazy_static! {
+        static ref GALLERY_REGEX: Regex = Regex::new(r"/g/(\d+)/([0-9a-f]+)").unwrap();
+    }
+    document
+        .select(".itg a")
+        .unwrap()
+        .filter_map(|a| {
+            let href = a.attributes.borrow().get("href")?.to_string();
+            let caps = GALLERY_REGEX.captures(&href)?;
+            let title = a.as_node().select_first(".glink").ok()?.text_contents();
+            Some((format!("{}/{}", &caps[1], &caps[2]), title))
+        })
+        .collect()
+}
+
+fn extract_next_cursor(document: &NodeRef) -> Option<String> {
+    let unext = document.select_first("a#unext").ok()?;
+    let href = unext.attributes.borrow().get("href")?.to_string();
+    lazy_static! {
+        static ref NEXT_REGEX: Regex = Regex::new(r"[?&]next=([^&]+)").unwrap();
+    }
+    NEXT_REGEX.captures(&href).map(|caps| caps[1].to_string())
 }
 
 fn extract_image_page_urls(document: &NodeRef) -> Vec<String> {
@@ -231,6 +1223,36 @@ fn extract_image_url(document: &NodeRef) -> String {
         .to_string()
 }
 
+fn extract_original_url(document: &NodeRef) -> Option<String> {
+    document
+        .select("#i6 a")
+        .unwrap()
+        .filter_map(|a| a.attributes.borrow().get("href").map(String::from))
+        .find(|href| href.contains("/fullimg"))
+}
+
+fn extract_original_ext(document: &NodeRef) -> Option<String> {
+    // The image info looks like `001.png :: 2400 x 3400 :: 2.3 MiB`.
+    let info = document
+        .select_first("#i2 > div + div")
+        .ok()?
+        .text_contents();
+    lazy_static! {
+        static ref INFO_REGEX: Regex = Regex::new(r"(\.[[:alnum:]]+) :: ").unwrap();
+    }
+    INFO_REGEX
+        .captures(&info)
+        .map(|caps| caps[1].to_lowercase())
+}
+
+fn extract_ext(url: &str) -> String {
+    lazy_static! {
+        static ref EXT_REGEX: Regex = Regex::new(r"\.[^\.]+$").unwrap();
+    }
+    let caps = EXT_REGEX.captures(url).unwrap();
+    caps[0].to_string()
+}
+
 fn extract_reload_value(document: &NodeRef) -> String {
     let loadfail = document
         .select_first("#loadfail")
@@ -246,3 +1268,149 @@ fn extract_reload_value(document: &NodeRef) -> String {
     let caps = RELOAD_REGEX.captures(&loadfail).unwrap();
     caps[1].to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_magnet, extract_archive_url, extract_gallery_list, extract_image_limit,
+        extract_metadata_title, extract_mpv, extract_newer_version, extract_next_cursor,
+        extract_original_ext, extract_original_url, extract_page_token, extract_torrents,
+    };
+    use kuchiki::traits::*;
+    use serde_json::json;
+
+    #[test]
+    fn gallery_list() {
+        let document = kuchiki::parse_html().one(
+            r#"<table class="itg gltc"><tr>
+                <td class="gl3c glname"><a href="https://exhentai.org/g/123/abcdef0123/">
+                    <div class="glink">Title A</div></a></td>
+            </tr><tr>
+                <td class="gl2c"><a href="https://exhentai.org/g/456/0123abcdef/"><img></a></td>
+                <td class="gl3c glname"><a href="https://exhentai.org/g/456/0123abcdef/">
+                    <div class="glink">Title B</div></a></td>
+            </tr></table>
+            <a id="unext" href="https://exhentai.org/?f_search=abc&amp;next=456">Next</a>"#,
+        );
+        assert_eq!(
+            extract_gallery_list(&document),
+            vec![
+                (String::from("123/abcdef0123"), String::from("Title A")),
+                (String::from("456/0123abcdef"), String::from("Title B")),
+            ]
+        );
+        assert_eq!(extract_next_cursor(&document), Some(String::from("456")));
+    }
+
+    #[test]
+    fn last_page() {
+        let document = kuchiki::parse_html().one(r#"<span id="unext">Next</span>"#);
+        assert_eq!(extract_next_cursor(&document), None);
+    }
+
+    #[test]
+    fn original_image() {
+        let document = kuchiki::parse_html().one(
+            r#"<div id="i2"><div class="sn"></div><div>001.PNG :: 2400 x 3400 :: 2.3 MiB</div></div>
+            <div id="i6"><div><a href="https://exhentai.org/?f_shash=abc">Show galleries</a></div>
+            <div><a href="https://exhentai.org/fullimg/123/1/abc/001.png">Download original</a></div></div>"#,
+        );
+        assert_eq!(
+            extract_original_url(&document),
+            Some(String::from(
+                "https://exhentai.org/fullimg/123/1/abc/001.png"
+            ))
+        );
+        assert_eq!(extract_original_ext(&document), Some(String::from(".png")));
+    }
+
+    #[test]
+    fn image_limit() {
+        let page = r#"<div class="homebox"><p>You are currently at <strong>1,234</strong>
+            towards a limit of <strong>5,000</strong>.</p></div>"#;
+        assert_eq!(extract_image_limit(page), Some((1234, 5000)));
+        assert_eq!(extract_image_limit("<p>Nothing</p>"), None);
+    }
+
+    #[test]
+    fn archive() {
+        let page =
+            r#"<script>document.location = "http://1.2.3.4:8080/archive/123/abc/def/2";</script>"#;
+        assert_eq!(
+            extract_archive_url(page),
+            Some(String::from("http://1.2.3.4:8080/archive/123/abc/def/2"))
+        );
+    }
+
+    #[test]
+    fn torrents() {
+        let document = kuchiki::parse_html().one(
+            r#"<form method="post"><table>
+                <tr><td><span>Posted:</span> <span>2021-01-01 10:00</span></td>
+                    <td><span>Seeds:</span> 3</td></tr>
+                <tr><td><a href="https://exhentai.org/torrent/1/0123456789abcdef0123456789abcdef01234567.torrent">Old Torrent</a></td></tr>
+            </table></form>
+            <form method="post"><table>
+                <tr><td><span>Posted:</span> <span>2022-02-02 20:00</span></td>
+                    <td><span>Seeds:</span> 3</td></tr>
+                <tr><td><a href="https://exhentai.org/torrent/1/fedcba9876543210fedcba9876543210fedcba98.torrent">New Torrent</a></td></tr>
+            </table></form>"#,
+        );
+        let torrents = extract_torrents(&document);
+        assert_eq!(torrents.len(), 2);
+        assert_eq!(torrents[1].posted, "2022-02-02 20:00");
+        assert_eq!(torrents[1].seeds, 3);
+        assert_eq!(
+            build_magnet(&torrents[1]),
+            Some(String::from(
+                "magnet:?xt=urn:btih:fedcba9876543210fedcba9876543210fedcba98&dn=New+Torrent"
+            ))
+        );
+    }
+
+    #[test]
+    fn mpv() {
+        let page = r#"<script type="text/javascript">
+            var base_url = "https://exhentai.org/";
+            var api_url = "https://s.exhentai.org/api.php";
+            var gid = 123;
+            var mpvkey = "abcdef";
+            var pagecount = 2;
+            var imagelist = [{"n":"001.jpg","k":"0123456789","t":"(1)"},{"n":"002.png","k":"9876543210","t":"(2)"}];
+            </script>"#;
+        let mpv = extract_mpv(page, "https://exhentai.org").unwrap();
+        assert_eq!(mpv.gid, 123);
+        assert_eq!(mpv.key, "abcdef");
+        assert_eq!(mpv.api_url, "https://s.exhentai.org/api.php");
+        assert_eq!(mpv.images.len(), 2);
+        assert_eq!(mpv.images[1].page, 2);
+        assert_eq!(mpv.images[1].key, "9876543210");
+        assert_eq!(mpv.images[1].name, "002.png");
+        assert!(extract_mpv("<p>Disabled</p>", "https://exhentai.org").is_none());
+    }
+
+    #[test]
+    fn metadata_title() {
+        let metadata = json!({"title": "[Artist] Title &amp; More", "title_jpn": ""});
+        assert_eq!(extract_metadata_title(&metadata), "[Artist] Title & More");
+        let metadata = json!({"title": "[Artist] Title", "title_jpn": "[作者] タイトル"});
+        assert_eq!(extract_metadata_title(&metadata), "[作者] タイトル");
+    }
+
+    #[test]
+    fn newer_version() {
+        let document = kuchiki::parse_html().one(
+            r#"<div id="gnd">There are newer versions of this gallery available:<br>
+                <a href="https://exhentai.org/g/200/aaaaaaaaaa/">Title</a>, added 2021-01-01 10:00<br>
+                <a href="https://exhentai.org/g/300/bbbbbbbbbb/">Title</a>, added 2022-01-01 10:00</div>"#,
+        );
+        assert_eq!(
+            extract_newer_version(&document),
+            Some((300, String::from("bbbbbbbbbb")))
+        );
+        assert_eq!(
+            extract_page_token("https://exhentai.org/s/0123456789/300-1"),
+            Some(String::from("0123456789"))
+        );
+    }
+}
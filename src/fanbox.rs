@@ -1,11 +1,274 @@
 use crate::crawler::Crawler;
+use crate::post::{save_post, Item, Kind};
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::fs::{self, File};
-use std::io::Write;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::path::PathBuf;
 
-pub fn crawl_posts(crawler: &Crawler, output: PathBuf, posts: Vec<String>) {
+pub fn crawl_creators(
+    crawler: &Crawler,
+    output: PathBuf,
+    download_links: bool,
+    creators: Vec<String>,
+) {
+    crawl_creators_with_plans(crawler, output, download_links, creators, &HashMap::new());
+}
+
+fn crawl_creators_with_plans(
+    crawler: &Crawler,
+    output: PathBuf,
+    download_links: bool,
+    creators: Vec<String>,
+    plans: &HashMap<String, Value>,
+) {
+    for creator in creators {
+        // Process the creator id and the range.
+        let parts: Vec<_> = creator.split('/').collect();
+        let (id, range) = match parts[..] {
+            [id] => (id, None),
+            [id, range] => (id, Some(range)),
+            _ => {
+                println!("Invalid Creator {creator}");
+                continue;
+            }
+        };
+
+        // Crawl the creator's profile.
+        let creator_result = crawler
+            .get_json(
+                "",
+                vec![(
+                    &format!("https://api.fanbox.cc/creator.get?creatorId={id}"),
+                    Vec::new(),
+                )],
+            )
+            .pop()
+            .unwrap();
+        let user = match creator_result {
+            Ok(json) => json["body"]["user"]["name"].as_str().unwrap().to_string(),
+            Err(err) => {
+                println!("Fail to crawl the profile for Creator {id}: {err}");
+                continue;
+            }
+        };
+
+        // Crawl the post index.
+        let posts = match crawl_post_index(crawler, id) {
+            Some(posts) => posts,
+            None => continue,
+        };
+        let mut posts: Vec<_> = posts
+            .into_iter()
+            .filter_map(|post| {
+                let post_id = post["id"].as_str().unwrap().to_string();
+                if post["isRestricted"].as_bool().unwrap_or(false) {
+                    println!("Skip restricted Post {post_id} for Creator {id}");
+                    None
+                } else {
+                    Some(post_id)
+                }
+            })
+            .collect();
+
+        // Create the creator directory.
+        let mut directory_path = output.clone();
+        let directory = sanitize_filename::sanitize(format!("[{user}]"));
+        directory_path.push(&directory);
+        fs::create_dir(&directory_path).unwrap();
+
+        // Crawl posts in the specified range.
+        let (start, end) = if let Some(range) = range {
+            let parts: Vec<_> = range.split('-').collect();
+            if parts.len() != 2 {
+                println!("Invalid range for Creator {creator}");
+                continue;
+            }
+            let start = parts[0].parse().unwrap();
+            let end = parts[1].parse().unwrap();
+            (start, end)
+        } else {
+            (1, posts.len())
+        };
+        let total = end - start + 1;
+        println!("{user} - {total} Posts");
+        crawl_posts_with_plans(
+            crawler,
+            directory_path,
+            download_links,
+            posts.drain(start - 1..end).collect(),
+            plans,
+        );
+    }
+}
+
+pub fn crawl_supporting(crawler: &Crawler, output: PathBuf, download_links: bool) {
+    let plans = match crawl_plans(crawler) {
+        Some(plans) => plans,
+        None => return,
+    };
+
+    // Crawl each creator.
+    let mut creators: Vec<_> = plans.keys().cloned().collect();
+    creators.sort_unstable();
+    for creator in creators {
+        let plan = &plans[&creator];
+        println!(
+            "{} - {} ({} JPY)",
+            plan["user"].as_str().unwrap(),
+            plan["title"].as_str().unwrap(),
+            plan["fee"]
+        );
+        crawl_creators_with_plans(
+            crawler,
+            output.clone(),
+            download_links,
+            vec![creator],
+            &plans,
+        );
+    }
+}
+
+pub fn crawl_feed(crawler: &Crawler, output: PathBuf, download_links: bool, since: Option<String>) {
+    let plans = crawl_plans(crawler).unwrap_or_default();
+
+    // Walk the feed until reaching posts older than the specified date.
+    let mut posts = Vec::new();
+    let mut next_url = Some(String::from(
+        "https://api.fanbox.cc/post.listSupporting?limit=10",
+    ));
+    'feed: while let Some(url) = next_url.take() {
+        let feed_result = crawler
+            .get_json("", vec![(&url, Vec::new())])
+            .pop()
+            .unwrap();
+        let json = match feed_result {
+            Ok(json) => json,
+            Err(err) => {
+                println!("Fail to crawl the feed: {err}");
+                break;
+            }
+        };
+        for post in json["body"]["items"].as_array().unwrap() {
+            let date = post["publishedDatetime"].as_str().unwrap();
+            if let Some(since) = &since {
+                if date < since.as_str() {
+                    break 'feed;
+                }
+            }
+            let id = post["id"].as_str().unwrap().to_string();
+            if post["isRestricted"].as_bool().unwrap_or(false) {
+                println!("Skip restricted Post {id}");
+            } else {
+                posts.push(id);
+            }
+        }
+        next_url = json["body"]["nextUrl"].as_str().map(String::from);
+    }
+
+    println!("Feed - {} Posts", posts.len());
+    crawl_posts_with_plans(crawler, output, download_links, posts, &plans);
+}
+
+fn crawl_plans(crawler: &Crawler) -> Option<HashMap<String, Value>> {
+    // Crawl plans we are supporting, which are recorded in post metadata.
+    let plan_result = crawler
+        .get_json(
+            "",
+            vec![("https://api.fanbox.cc/plan.listSupporting", Vec::new())],
+        )
+        .pop()
+        .unwrap();
+    match plan_result {
+        Ok(json) => Some(
+            json["body"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|plan| {
+                    let creator = plan["creatorId"].as_str().unwrap().to_string();
+                    let plan = json!({
+                        "user": plan["user"]["name"],
+                        "title": plan["title"],
+                        "fee": plan["fee"],
+                    });
+                    (creator, plan)
+                })
+                .collect(),
+        ),
+        Err(err) => {
+            println!("Fail to crawl supporting plans: {err}");
+            None
+        }
+    }
+}
+
+fn crawl_post_index(crawler: &Crawler, creator: &str) -> Option<Vec<Value>> {
+    // Crawl the pagination urls.
+    let paginate_result = crawler
+        .get_json(
+            "",
+            vec![(
+                &format!("https://api.fanbox.cc/post.paginateCreator?creatorId={creator}"),
+                Vec::new(),
+            )],
+        )
+        .pop()
+        .unwrap();
+    let page_urls: Vec<_> = match paginate_result {
+        Ok(json) => json["body"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|url| url.as_str().unwrap().to_string())
+            .collect(),
+        Err(err) => {
+            println!("Fail to crawl the post pagination for Creator {creator}: {err}");
+            return None;
+        }
+    };
+
+    // Crawl post lists.
+    let page_requests = page_urls
+        .iter()
+        .map(|url| (url.as_str(), Vec::new()))
+        .collect();
+    let page_results = crawler.get_json("Post Lists", page_requests);
+    let mut posts = Vec::new();
+    for (i, page_result) in page_results.into_iter().enumerate() {
+        match page_result {
+            Ok(json) => {
+                let items = json["body"]
+                    .get("items")
+                    .unwrap_or(&json["body"])
+                    .as_array()
+                    .unwrap();
+                posts.extend(items.iter().cloned());
+            }
+            Err(err) => {
+                println!(
+                    "Fail to crawl post list {} for Creator {creator}: {err}",
+                    i + 1
+                );
+                return None;
+            }
+        }
+    }
+    Some(posts)
+}
+
+pub fn crawl_posts(crawler: &Crawler, output: PathBuf, download_links: bool, posts: Vec<String>) {
+    crawl_posts_with_plans(crawler, output, download_links, posts, &HashMap::new());
+}
+
+fn crawl_posts_with_plans(
+    crawler: &Crawler,
+    output: PathBuf,
+    download_links: bool,
+    posts: Vec<String>,
+    plans: &HashMap<String, Value>,
+) {
     // Crawl the info json.
     let post_urls: Vec<_> = posts
         .iter()
@@ -41,73 +304,307 @@ pub fn crawl_posts(crawler: &Crawler, output: PathBuf, posts: Vec<String>) {
         };
         let title = info["body"]["title"].as_str().unwrap();
         let name = sanitize_filename::sanitize(format!("[{user}] [{date}] {title}"));
-        let output = output.join(&name);
 
-        // Create a directory if there is more than one image.
-        let image_urls: Vec<_> = if let Some(images) = info["body"]["body"].get("images") {
-            images
-                .as_array()
-                .unwrap()
-                .iter()
-                .map(|img| img.as_object().unwrap())
-                .map(|img| {
-                    (
-                        img["originalUrl"].as_str().unwrap(),
-                        img["extension"].as_str().unwrap(),
-                    )
-                })
-                .collect()
-        } else {
-            let image_map = info["body"]["body"]["imageMap"].as_object().unwrap();
-            info["body"]["body"]["blocks"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .filter_map(|blk| {
-                    let blk = blk.as_object().unwrap();
-                    if blk["type"] == "image" {
+        // Collect images, attachments and text in this post.
+        let body = &info["body"]["body"];
+        if body.is_null() {
+            println!("Skip restricted Post {id}");
+            continue;
+        }
+        let mut images = Vec::new();
+        let mut files = Vec::new();
+        let mut links = Vec::new();
+        let mut text = String::new();
+        if let Some(blocks) = body.get("blocks") {
+            for blk in blocks.as_array().unwrap() {
+                match blk["type"].as_str().unwrap() {
+                    "image" => {
                         let image_id = blk["imageId"].as_str().unwrap();
-                        Some((
-                            image_map[image_id]["originalUrl"].as_str().unwrap(),
-                            image_map[image_id]["extension"].as_str().unwrap(),
-                        ))
-                    } else {
-                        None
+                        images.push(&body["imageMap"][image_id]);
                     }
-                })
-                .collect()
-        };
-        if image_urls.len() > 1 {
-            fs::create_dir(&output).unwrap();
-        };
+                    "file" => {
+                        let file_id = blk["fileId"].as_str().unwrap();
+                        files.push(&body["fileMap"][file_id]);
+                    }
+                    "embed" => {
+                        let embed = &body["embedMap"][blk["embedId"].as_str().unwrap()];
+                        links.extend(extract_embed_url(
+                            embed["serviceProvider"].as_str().unwrap(),
+                            embed["contentId"].as_str().unwrap(),
+                        ));
+                    }
+                    "url_embed" => {
+                        let url_embed_id = blk["urlEmbedId"].as_str().unwrap();
+                        links.extend(extract_url_embed_url(&body["urlEmbedMap"][url_embed_id]));
+                    }
+                    "p" => text.push_str(&format!("{}\n\n", convert_text_block(blk))),
+                    "header" => text.push_str(&format!("## {}\n\n", convert_text_block(blk))),
+                    _ => (),
+                }
+                if let Some(lnks) = blk.get("links").and_then(|lnks| lnks.as_array()) {
+                    links.extend(
+                        lnks.iter()
+                            .map(|lnk| lnk["url"].as_str().unwrap().to_string()),
+                    );
+                }
+            }
+        } else {
+            if let Some(imgs) = body.get("images") {
+                images.extend(imgs.as_array().unwrap());
+            }
+            if let Some(fls) = body.get("files") {
+                files.extend(fls.as_array().unwrap());
+            }
+            if let Some(video) = body.get("video") {
+                links.extend(extract_embed_url(
+                    video["serviceProvider"].as_str().unwrap(),
+                    video["videoId"].as_str().unwrap(),
+                ));
+            }
+            if let Some(txt) = body["text"].as_str() {
+                text.push_str(txt);
+            }
+        }
+        let mut seen = HashSet::new();
+        links.retain(|link| seen.insert(link.clone()));
 
-        // Crawl images in this post.
-        let image_requests = image_urls
+        // Name images by their indexes and attachments by their original names.
+        let mut items: Vec<_> = images
             .iter()
-            .map(|(url, _)| (*url, Vec::new()))
+            .enumerate()
+            .map(|(i, img)| Item {
+                url: img["originalUrl"].as_str().unwrap().to_string(),
+                name: format!("{:0>4}", i + 1),
+                ext: format!(".{}", img["extension"].as_str().unwrap()),
+                kind: Kind::Image,
+            })
+            .chain(files.iter().map(|file| Item {
+                url: file["url"].as_str().unwrap().to_string(),
+                name: sanitize_filename::sanitize(file["name"].as_str().unwrap()),
+                ext: format!(".{}", file["extension"].as_str().unwrap()),
+                kind: Kind::File,
+            }))
             .collect();
-        let image_results = crawler.get_byte(&name, image_requests);
-
-        // Write images to local files.
-        for (i, ((_, ext), image)) in image_urls.iter().zip(image_results).enumerate() {
-            let image = match image {
-                Ok(image) => image,
-                Err(err) => {
-                    println!("Fail to crawl Image {} for Post {id}: {err}", i + 1);
-                    continue;
-                }
-            };
-            if image_urls.len() == 1 {
-                let mut output = output.clone().into_os_string();
-                output.push(format!(".{ext}"));
-                let mut file = File::create(&output).unwrap();
-                file.write_all(&image).unwrap();
-            } else {
-                let mut path = output.clone();
-                path.push(format!("{:0>4}.{ext}", i + 1));
-                let mut file = File::create(path).unwrap();
-                file.write_all(&image).unwrap();
+        if download_links {
+            items.extend(links.iter().filter_map(|link| {
+                let (url, name, ext) = convert_direct_link(link)?;
+                Some(Item {
+                    url,
+                    name,
+                    ext,
+                    kind: Kind::Link,
+                })
+            }));
+        }
+
+        // Save the post text as Markdown, links as text and the metadata as JSON.
+        let plan = info["body"]["creatorId"]
+            .as_str()
+            .and_then(|creator| plans.get(creator));
+        let metadata = json!({
+            "id": id,
+            "creatorId": info["body"]["creatorId"],
+            "user": user,
+            "title": title,
+            "publishedDatetime": info["body"]["publishedDatetime"],
+            "feeRequired": info["body"]["feeRequired"],
+            "supportingPlan": plan,
+            "links": links,
+        });
+        let sidecars = vec![
+            ("post.md", ".md", text.trim().to_string()),
+            ("links.txt", ".links.txt", links.join("\n")),
+        ];
+        save_post(crawler, &output, &name, id, items, sidecars, &metadata);
+    }
+}
+
+pub fn parse_date(date: &str) -> Result<String, String> {
+    // Dates are compared with ISO 8601 datetimes as strings.
+    lazy_static! {
+        static ref DATE_REGEX: Regex = Regex::new(r"^[0-9]{4}-([0-9]{2})-([0-9]{2})$").unwrap();
+    }
+    match DATE_REGEX.captures(date) {
+        Some(caps)
+            if (1..=12).contains(&caps[1].parse().unwrap())
+                && (1..=31).contains(&caps[2].parse().unwrap()) =>
+        {
+            Ok(date.to_string())
+        }
+        _ => Err(String::from("Date should look like `yyyy-mm-dd`")),
+    }
+}
+
+fn extract_embed_url(provider: &str, id: &str) -> Option<String> {
+    match provider {
+        "youtube" => Some(format!("https://www.youtube.com/watch?v={id}")),
+        "vimeo" => Some(format!("https://vimeo.com/{id}")),
+        "soundcloud" => Some(format!("https://soundcloud.com/{id}")),
+        "twitter" => Some(format!("https://twitter.com/i/web/status/{id}")),
+        "gist" => Some(format!("https://gist.github.com/{id}")),
+        "google_forms" => Some(format!("https://docs.google.com/forms/d/e/{id}/viewform")),
+        _ => None,
+    }
+}
+
+fn extract_url_embed_url(url_embed: &Value) -> Option<String> {
+    match url_embed["type"].as_str()? {
+        "default" => url_embed["url"].as_str().map(String::from),
+        "html" | "html.card" => {
+            lazy_static! {
+                static ref SRC_REGEX: Regex = Regex::new(r#"(?:src|href)="([^"]+)""#).unwrap();
             }
+            let html = url_embed["html"].as_str()?;
+            SRC_REGEX.captures(html).map(|caps| caps[1].to_string())
+        }
+        "fanbox.post" => {
+            let post = &url_embed["postInfo"];
+            let creator = post["creatorId"].as_str()?;
+            let id = post["id"].as_str()?;
+            Some(format!("https://www.fanbox.cc/@{creator}/posts/{id}"))
+        }
+        "fanbox.creator" => {
+            let creator = url_embed["profile"]["creatorId"].as_str()?;
+            Some(format!("https://www.fanbox.cc/@{creator}"))
+        }
+        _ => None,
+    }
+}
+
+fn convert_direct_link(link: &str) -> Option<(String, String, String)> {
+    // Only file hosts serving files without any script are supported, so MEGA
+    // links, which are decrypted in the browser, are only recorded.
+    lazy_static! {
+        static ref DRIVE_REGEX: Regex =
+            Regex::new(r"^https://drive\.google\.com/(?:file/d/|open\?id=)([\w-]+)").unwrap();
+        static ref DROPBOX_REGEX: Regex = Regex::new(
+            r"^(https://www\.dropbox\.com/[^?#]+/([^/?#]+?)(\.[^./?#]+)?)(?:\?([^#]*))?(?:#|$)"
+        )
+        .unwrap();
+    }
+    if let Some(caps) = DRIVE_REGEX.captures(link) {
+        let id = &caps[1];
+        Some((
+            format!("https://drive.google.com/uc?export=download&id={id}"),
+            sanitize_filename::sanitize(id),
+            String::new(),
+        ))
+    } else {
+        // Shared links may carry keys like `rlkey` in the query, so only the
+        // `dl` parameter is replaced.
+        DROPBOX_REGEX.captures(link).map(|caps| {
+            let mut queries: Vec<_> = caps
+                .get(4)
+                .map_or("", |queries| queries.as_str())
+                .split('&')
+                .filter(|query| !query.is_empty() && !query.starts_with("dl="))
+                .collect();
+            queries.push("dl=1");
+            (
+                format!("{}?{}", &caps[1], queries.join("&")),
+                sanitize_filename::sanitize(&caps[2]),
+                caps.get(3).map_or("", |ext| ext.as_str()).to_string(),
+            )
+        })
+    }
+}
+
+fn convert_text_block(block: &Value) -> String {
+    // Collect Markdown marks for bold styles and links. The offsets are counted
+    // in UTF-16 code units, and marks at the same offset are ordered so that
+    // closing marks come first and links enclose bold texts.
+    let text: Vec<u16> = block["text"].as_str().unwrap().encode_utf16().collect();
+    let mut marks = Vec::new();
+    if let Some(styles) = block.get("styles").and_then(|styles| styles.as_array()) {
+        for style in styles.iter().filter(|style| style["type"] == "bold") {
+            let offset = style["offset"].as_u64().unwrap() as usize;
+            let length = style["length"].as_u64().unwrap() as usize;
+            marks.push((offset, 3, String::from("**")));
+            marks.push((offset + length, 0, String::from("**")));
         }
     }
+    if let Some(links) = block.get("links").and_then(|links| links.as_array()) {
+        for link in links {
+            let offset = link["offset"].as_u64().unwrap() as usize;
+            let length = link["length"].as_u64().unwrap() as usize;
+            let url = link["url"].as_str().unwrap();
+            marks.push((offset, 2, String::from("[")));
+            marks.push((offset + length, 1, format!("]({url})")));
+        }
+    }
+    marks.sort_by_key(|(offset, rank, _)| (*offset, *rank));
+
+    // Insert marks into the text.
+    let mut markdown = String::new();
+    let mut last = 0;
+    for (offset, _, mark) in marks {
+        let offset = offset.min(text.len());
+        markdown.push_str(&String::from_utf16_lossy(&text[last..offset]));
+        markdown.push_str(&mark);
+        last = offset;
+    }
+    markdown.push_str(&String::from_utf16_lossy(&text[last..]));
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_direct_link, convert_text_block, parse_date};
+    use serde_json::json;
+
+    #[test]
+    fn plain_text_block() {
+        let block = json!({"type": "p", "text": "こんにちは"});
+        assert_eq!(convert_text_block(&block), "こんにちは");
+    }
+
+    #[test]
+    fn styled_text_block() {
+        let block = json!({
+            "type": "p",
+            "text": "新作 is here",
+            "styles": [{"type": "bold", "offset": 0, "length": 2}],
+            "links": [{"offset": 0, "length": 10, "url": "https://example.com"}],
+        });
+        assert_eq!(
+            convert_text_block(&block),
+            "[**新作** is here](https://example.com)"
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_date("2024-01-06").unwrap(), "2024-01-06");
+        assert!(parse_date("2024/01/06").is_err());
+        assert!(parse_date("2024-13-06").is_err());
+    }
+
+    #[test]
+    fn direct_links() {
+        assert_eq!(
+            convert_direct_link("https://drive.google.com/file/d/1aB-c_D/view?usp=sharing"),
+            Some((
+                String::from("https://drive.google.com/uc?export=download&id=1aB-c_D"),
+                String::from("1aB-c_D"),
+                String::new(),
+            ))
+        );
+        assert_eq!(
+            convert_direct_link("https://www.dropbox.com/s/abc123/pack.zip?dl=0"),
+            Some((
+                String::from("https://www.dropbox.com/s/abc123/pack.zip?dl=1"),
+                String::from("pack"),
+                String::from(".zip"),
+            ))
+        );
+        assert_eq!(
+            convert_direct_link("https://www.dropbox.com/scl/fi/xyz789/pack.zip?rlkey=k1&dl=0"),
+            Some((
+                String::from("https://www.dropbox.com/scl/fi/xyz789/pack.zip?rlkey=k1&dl=1"),
+                String::from("pack"),
+                String::from(".zip"),
+            ))
+        );
+        assert_eq!(convert_direct_link("https://mega.nz/file/abc#key"), None);
+    }
 }
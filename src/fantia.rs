@@ -0,0 +1,270 @@
+use crate::crawler::Crawler;
+use crate::post::{save_post, Item, Kind};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+
+lazy_static! {
+    static ref POST_REGEX: Regex = Regex::new(r#"href="/posts/([0-9]+)""#).unwrap();
+    static ref DATE_REGEX: Regex =
+        Regex::new(r"([0-9]{2}) ([A-Z][a-z]{2}) [0-9]{2}([0-9]{2})").unwrap();
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub fn crawl_fanclubs(crawler: &Crawler, output: PathBuf, fanclubs: Vec<String>) {
+    for fanclub in fanclubs {
+        // Process the fanclub id and the range.
+        let parts: Vec<_> = fanclub.split('/').collect();
+        let (id, range) = match parts[..] {
+            [id] => (id, None),
+            [id, range] => (id, Some(range)),
+            _ => {
+                println!("Invalid Fanclub {fanclub}");
+                continue;
+            }
+        };
+
+        // Crawl the fanclub's profile.
+        let fanclub_result = crawler
+            .get_json(
+                "",
+                vec![(
+                    &format!("https://fantia.jp/api/v1/fanclubs/{id}"),
+                    Vec::new(),
+                )],
+            )
+            .pop()
+            .unwrap();
+        let user = match fanclub_result {
+            Ok(json) => json["fanclub"]["creator_name"]
+                .as_str()
+                .unwrap()
+                .to_string(),
+            Err(err) => {
+                println!("Fail to crawl the profile for Fanclub {id}: {err}");
+                continue;
+            }
+        };
+
+        // Crawl the post index, which is only available as HTML pages.
+        let mut posts = Vec::new();
+        for page in 1.. {
+            let page_result = crawler
+                .get_text(
+                    "",
+                    vec![(
+                        &format!("https://fantia.jp/fanclubs/{id}/posts"),
+                        vec![("page", &page.to_string())],
+                    )],
+                )
+                .pop()
+                .unwrap();
+            let page_posts = match page_result {
+                Ok(html) => extract_post_ids(&html),
+                Err(err) => {
+                    println!("Fail to crawl post page {page} for Fanclub {id}: {err}");
+                    break;
+                }
+            };
+            let total = posts.len();
+            for post in page_posts {
+                if !posts.contains(&post) {
+                    posts.push(post);
+                }
+            }
+            if posts.len() == total {
+                break;
+            }
+        }
+
+        // Create the fanclub directory.
+        let mut directory_path = output.clone();
+        let directory = sanitize_filename::sanitize(format!("[{user}]"));
+        directory_path.push(&directory);
+        fs::create_dir(&directory_path).unwrap();
+
+        // Crawl posts in the specified range.
+        let (start, end) = if let Some(range) = range {
+            let parts: Vec<_> = range.split('-').collect();
+            if parts.len() != 2 {
+                println!("Invalid range for Fanclub {fanclub}");
+                continue;
+            }
+            let start = parts[0].parse().unwrap();
+            let end = parts[1].parse().unwrap();
+            (start, end)
+        } else {
+            (1, posts.len())
+        };
+        let total = end - start + 1;
+        println!("{user} - {total} Posts");
+        crawl_posts(
+            crawler,
+            directory_path,
+            posts.drain(start - 1..end).collect(),
+        );
+    }
+}
+
+pub fn crawl_posts(crawler: &Crawler, output: PathBuf, posts: Vec<String>) {
+    // Crawl the info json.
+    let post_urls: Vec<_> = posts
+        .iter()
+        .map(|id| format!("https://fantia.jp/api/v1/posts/{id}"))
+        .collect();
+    let post_requests = post_urls
+        .iter()
+        .map(|url| (url.as_str(), Vec::new()))
+        .collect();
+    let post_results = crawler.get_json("Post Infos", post_requests);
+    let posts = posts
+        .iter()
+        .zip(post_results)
+        .filter_map(|(id, info)| match info {
+            Ok(info) => Some((id, info)),
+            Err(err) => {
+                println!("Fail to crawl the info json for Post {id}: {err}");
+                None
+            }
+        });
+
+    for (id, info) in posts {
+        // Extract basic information.
+        let post = &info["post"];
+        let user = post["fanclub"]["creator_name"].as_str().unwrap();
+        let date = convert_date(post["posted_at"].as_str().unwrap()).unwrap_or_default();
+        let title = post["title"].as_str().unwrap();
+        let name = sanitize_filename::sanitize(format!("[{user}] [{date}] {title}"));
+
+        // Collect photos, downloads and text in visible contents. Contents of
+        // plans we are not supporting are skipped.
+        let mut images = Vec::new();
+        let mut files = Vec::new();
+        let mut text = String::new();
+        if let Some(comment) = post["comment"].as_str() {
+            text.push_str(&format!("{comment}\n\n"));
+        }
+        let contents = post["post_contents"].as_array().unwrap();
+        let mut visible = 0;
+        for content in contents {
+            let content_id = &content["id"];
+            if content["visible_status"].as_str() != Some("visible") {
+                let plan = content["plan"]["name"].as_str().unwrap_or_default();
+                println!("Skip restricted Content {content_id} of Post {id} ({plan})");
+                continue;
+            }
+            visible += 1;
+            match content["category"].as_str().unwrap() {
+                "photo_gallery" => {
+                    for photo in content["post_content_photos"].as_array().unwrap() {
+                        images.push(photo["url"]["original"].as_str().unwrap());
+                    }
+                }
+                "file" => {
+                    let uri = content["download_uri"].as_str().unwrap();
+                    let file_name = content["filename"].as_str().unwrap();
+                    files.push((format!("https://fantia.jp{uri}"), file_name));
+                }
+                "text" => {
+                    if let Some(comment) = content["comment"].as_str() {
+                        text.push_str(&format!("{comment}\n\n"));
+                    }
+                }
+                _ => (),
+            }
+        }
+        if visible == 0 && !contents.is_empty() {
+            println!("Skip restricted Post {id}");
+            continue;
+        }
+
+        // Name images by their indexes and downloads by their original names.
+        let items: Vec<_> = images
+            .iter()
+            .enumerate()
+            .map(|(i, url)| Item {
+                url: url.to_string(),
+                name: format!("{:0>4}", i + 1),
+                ext: extract_ext(url).to_string(),
+                kind: Kind::Image,
+            })
+            .chain(files.iter().map(|(url, file_name)| {
+                let ext = extract_ext(file_name);
+                Item {
+                    url: url.clone(),
+                    name: sanitize_filename::sanitize(&file_name[..file_name.len() - ext.len()]),
+                    ext: ext.to_string(),
+                    kind: Kind::File,
+                }
+            }))
+            .collect();
+
+        // Save the post text as Markdown and the metadata as JSON.
+        let metadata = json!({
+            "id": id,
+            "fanclubId": post["fanclub"]["id"],
+            "user": user,
+            "title": title,
+            "postedAt": post["posted_at"],
+        });
+        let sidecars = vec![("post.md", ".md", text.trim().to_string())];
+        save_post(crawler, &output, &name, id, items, sidecars, &metadata);
+    }
+}
+
+fn extract_post_ids(html: &str) -> Vec<String> {
+    POST_REGEX
+        .captures_iter(html)
+        .map(|caps| caps[1].to_string())
+        .collect()
+}
+
+fn extract_ext(url: &str) -> &str {
+    // Photo urls are signed with queries.
+    let path = url.split('?').next().unwrap();
+    let name = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+    name.rfind('.').map_or("", |i| &name[i..])
+}
+
+fn convert_date(date: &str) -> Option<String> {
+    // Dates look like `Sat, 06 Jan 2024 19:00:00 +0900`.
+    let caps = DATE_REGEX.captures(date)?;
+    let month = MONTHS.iter().position(|month| *month == &caps[2])? + 1;
+    Some(format!("{}{month:0>2}{}", &caps[3], &caps[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_date, extract_ext, extract_post_ids};
+
+    #[test]
+    fn post_ids() {
+        let html = r#"<a class="link-block" href="/posts/123"></a><a href="/posts/456"></a><a href="/fanclubs/1"></a>"#;
+        assert_eq!(extract_post_ids(html), vec!["123", "456"]);
+    }
+
+    #[test]
+    fn ext() {
+        assert_eq!(
+            extract_ext(
+                "https://cc.fantia.jp/uploads/post_content_photo/file/1/a.jpg?Key-Pair-Id=K"
+            ),
+            ".jpg"
+        );
+        assert_eq!(extract_ext("archive.tar.gz"), ".gz");
+        assert_eq!(extract_ext("README"), "");
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!(
+            convert_date("Sat, 06 Jan 2024 19:00:00 +0900").unwrap(),
+            "240106"
+        );
+    }
+}
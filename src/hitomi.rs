@@ -0,0 +1,219 @@
+use crate::crawler::{name_directory, Crawler};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+const DOMAIN: &str = "gold-usergeeks.com";
+
+lazy_static! {
+    static ref DEFAULT_REGEX: Regex = Regex::new(r"var o = (\d)").unwrap();
+    static ref CASE_REGEX: Regex = Regex::new(r"case (\d+):").unwrap();
+    static ref CASE_VALUE_REGEX: Regex = Regex::new(r"o = (\d); break;").unwrap();
+    static ref BASE_REGEX: Regex = Regex::new(r"b: '(\d+/)'").unwrap();
+}
+
+struct Rules {
+    default: usize,
+    value: usize,
+    cases: Vec<usize>,
+    base: String,
+}
+
+impl Rules {
+    fn image_url(&self, hash: &str) -> Option<String> {
+        // The last three hex digits select the subdomain and the path.
+        let len = hash.len();
+        if len < 3 {
+            return None;
+        }
+        let g = usize::from_str_radix(
+            &format!("{}{}", &hash[len - 1..], &hash[len - 3..len - 1]),
+            16,
+        )
+        .ok()?;
+        let m = if self.cases.contains(&g) {
+            self.value
+        } else {
+            self.default
+        };
+        Some(format!(
+            "https://w{}.{DOMAIN}/{}{g}/{hash}.webp",
+            m + 1,
+            self.base
+        ))
+    }
+}
+
+pub fn crawl_galleries(crawler: &Crawler, output: PathBuf, galleries: Vec<String>) {
+    // Crawl the subdomain and path rules, which are shared by galleries.
+    let gg_result = crawler
+        .get_text("", vec![("https://ltn.hitomi.la/gg.js", Vec::new())])
+        .pop()
+        .unwrap();
+    let rules = match gg_result.map(|gg| extract_rules(&gg)) {
+        Ok(Some(rules)) => rules,
+        Ok(None) => {
+            println!("Fail to parse gg.js");
+            return;
+        }
+        Err(err) => {
+            println!("Fail to crawl gg.js: {err}");
+            return;
+        }
+    };
+
+    for gallery in galleries {
+        // Process the gallery id and the range.
+        let parts: Vec<_> = gallery.split('/').collect();
+        let (id, range) = match parts[..] {
+            [id] => (id, None),
+            [id, range] => (id, Some(range)),
+            _ => {
+                println!("Invalid Gallery {gallery}");
+                continue;
+            }
+        };
+
+        // Crawl the gallery info.
+        let info_result = crawler
+            .get_text(
+                "",
+                vec![(
+                    &format!("https://ltn.hitomi.la/galleries/{id}.js"),
+                    Vec::new(),
+                )],
+            )
+            .pop()
+            .unwrap();
+        let info = match info_result.map(|info| extract_info(&info)) {
+            Ok(Some(info)) => info,
+            Ok(None) => {
+                println!("Fail to parse the info for Gallery {id}");
+                continue;
+            }
+            Err(err) => {
+                println!("Fail to crawl the info for Gallery {id}: {err}");
+                continue;
+            }
+        };
+
+        // Build image urls from file hashes.
+        let title = extract_title(&info);
+        let mut image_urls: Vec<_> = info["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|file| rules.image_url(file["hash"].as_str()?))
+            .collect();
+
+        // Determine a proper range.
+        let (start, end) = if let Some(range) = range {
+            let parts: Vec<_> = range.split('-').collect();
+            if parts.len() != 2 {
+                println!("Invalid range for Gallery {gallery}");
+                continue;
+            }
+            let start = parts[0].parse().unwrap();
+            let end = parts[1].parse().unwrap();
+            (start, end)
+        } else {
+            (1, image_urls.len())
+        };
+        let image_urls: Vec<_> = image_urls.drain(start - 1..end).collect();
+
+        // Create the gallery directory.
+        let directory = match name_directory(&output, &title, id) {
+            Some(directory) => directory,
+            None => {
+                println!("Skip Gallery {id}, which has been downloaded");
+                continue;
+            }
+        };
+        let mut directory_path = output.clone();
+        directory_path.push(&directory);
+        fs::create_dir(&directory_path).unwrap();
+        // Save the gallery info with the downloaded range as metadata.
+        let mut info = info;
+        info["range"] = json!([start, end]);
+        let file = File::create(directory_path.join("metadata.json")).unwrap();
+        serde_json::to_writer_pretty(file, &info).unwrap();
+
+        // Crawl images in this gallery.
+        let image_requests = image_urls
+            .iter()
+            .map(|url| (url.as_str(), Vec::new(), None))
+            .collect();
+        let image_results = crawler.get_image(&title, image_requests);
+
+        // Write images to local files, numbered from the start of the range as
+        // ExHentai galleries are.
+        for (i, result) in image_results.into_iter().enumerate() {
+            let pg = start + i;
+            let image = match result {
+                Ok(image) => image,
+                Err(err) => {
+                    println!("Fail to crawl page {pg} for Gallery {id}: {err}");
+                    continue;
+                }
+            };
+            let mut path = directory_path.clone();
+            path.push(format!("{:0>4}.webp", i + 1));
+            let mut file = File::create(path).unwrap();
+            file.write_all(&image).unwrap();
+        }
+    }
+}
+
+fn extract_rules(gg: &str) -> Option<Rules> {
+    Some(Rules {
+        default: DEFAULT_REGEX.captures(gg)?[1].parse().ok()?,
+        value: CASE_VALUE_REGEX.captures(gg)?[1].parse().ok()?,
+        cases: CASE_REGEX
+            .captures_iter(gg)
+            .filter_map(|cap| cap[1].parse().ok())
+            .collect(),
+        base: BASE_REGEX.captures(gg)?[1].to_string(),
+    })
+}
+
+fn extract_info(info: &str) -> Option<Value> {
+    let json = &info[info.find('{')?..];
+    serde_json::from_str(json).ok()
+}
+
+fn extract_title(info: &Value) -> String {
+    // Prefer the Japanese title as ExHentai does.
+    ["japanese_title", "title"]
+        .iter()
+        .filter_map(|key| info[key].as_str())
+        .find(|title| !title.is_empty())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_info, extract_rules, extract_title};
+
+    #[test]
+    fn rules() {
+        let gg = "'use strict';\ngg = { m: function(g) {\nvar o = 0;\nswitch (g) {\ncase 1939:\ncase 2643:\no = 1; break;\n}\nreturn o;\n}, s: function(h) { var m = /(..)(.)$/.exec(h); return parseInt(m[2]+m[1], 16).toString(10); }, b: '1700000000/'\n};";
+        let rules = extract_rules(gg).unwrap();
+        assert_eq!(rules.cases, vec![1939, 2643]);
+        let hash = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abc53a";
+        assert_eq!(
+            rules.image_url(hash).unwrap(),
+            format!("https://w2.gold-usergeeks.com/1700000000/2643/{hash}.webp")
+        );
+    }
+
+    #[test]
+    fn info() {
+        let info = r#"var galleryinfo = {"title":"Title","japanese_title":null,"files":[]}"#;
+        let info = extract_info(info).unwrap();
+        assert_eq!(extract_title(&info), "Title");
+    }
+}
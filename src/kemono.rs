@@ -0,0 +1,206 @@
+use crate::crawler::Crawler;
+use crate::post::{save_post, Item, Kind};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+const IMAGE_EXTS: [&str; 5] = [".jpg", ".jpeg", ".png", ".gif", ".webp"];
+
+lazy_static! {
+    static ref DATE_REGEX: Regex = Regex::new(r"([0-9]{2})-([0-9]{2})-([0-9]{2})").unwrap();
+}
+
+pub fn crawl_targets(crawler: &Crawler, output: PathBuf, targets: Vec<String>) {
+    for target in targets {
+        // Process the service, the user id and the post id.
+        let parts: Vec<_> = target.split('/').collect();
+        let (service, user, post) = match parts[..] {
+            [service, user] => (service, user, None),
+            [service, user, "post", post] => (service, user, Some(post)),
+            _ => {
+                println!("Invalid Target {target}");
+                continue;
+            }
+        };
+        let base_url = base_url(service);
+
+        // Crawl the user's profile.
+        let profile_result = crawler
+            .get_json(
+                "",
+                vec![(
+                    &format!("{base_url}/api/v1/{service}/user/{user}/profile"),
+                    Vec::new(),
+                )],
+            )
+            .pop()
+            .unwrap();
+        let name = match profile_result {
+            Ok(json) => json["name"].as_str().unwrap().to_string(),
+            Err(err) => {
+                println!("Fail to crawl the profile for User {user}: {err}");
+                continue;
+            }
+        };
+
+        // Crawl a single post, or all posts of the user.
+        if let Some(post) = post {
+            let post_result = crawler
+                .get_json(
+                    "",
+                    vec![(
+                        &format!("{base_url}/api/v1/{service}/user/{user}/post/{post}"),
+                        Vec::new(),
+                    )],
+                )
+                .pop()
+                .unwrap();
+            match post_result {
+                Ok(json) => {
+                    // Newer APIs wrap the post with its revisions.
+                    let post = json.get("post").unwrap_or(&json).clone();
+                    crawl_posts(crawler, output.clone(), base_url, &name, vec![post]);
+                }
+                Err(err) => println!("Fail to crawl the info json for Post {post}: {err}"),
+            }
+        } else {
+            let mut posts = Vec::new();
+            loop {
+                let page_result = crawler
+                    .get_json(
+                        "",
+                        vec![(
+                            &format!("{base_url}/api/v1/{service}/user/{user}/posts"),
+                            vec![("o", &posts.len().to_string())],
+                        )],
+                    )
+                    .pop()
+                    .unwrap();
+                let page = match page_result {
+                    Ok(json) => json.as_array().cloned().unwrap_or_default(),
+                    Err(err) => {
+                        println!("Fail to crawl posts for User {user}: {err}");
+                        break;
+                    }
+                };
+                if page.is_empty() {
+                    break;
+                }
+                posts.extend(page);
+            }
+
+            // Create the user directory.
+            let mut directory_path = output.clone();
+            let directory = sanitize_filename::sanitize(format!("[{name}]"));
+            directory_path.push(&directory);
+            fs::create_dir(&directory_path).unwrap();
+            println!("{name} - {} Posts", posts.len());
+            crawl_posts(crawler, directory_path, base_url, &name, posts);
+        }
+    }
+}
+
+fn crawl_posts(crawler: &Crawler, output: PathBuf, base_url: &str, user: &str, posts: Vec<Value>) {
+    for post in posts {
+        // Extract basic information.
+        let id = post["id"].as_str().unwrap();
+        let date = {
+            let date = post["published"].as_str().unwrap_or_default();
+            match DATE_REGEX.captures(date) {
+                Some(caps) => format!("{}{}{}", &caps[1], &caps[2], &caps[3]),
+                None => String::new(),
+            }
+        };
+        let title = post["title"].as_str().unwrap_or_default();
+        let name = sanitize_filename::sanitize(format!("[{user}] [{date}] {title}"));
+
+        // Name images by their indexes and attachments by their original
+        // names as FANBOX and Fantia do. Files are deduplicated by the hash
+        // in their paths.
+        let mut hashes = HashSet::new();
+        let mut items = Vec::new();
+        let mut pg = 0;
+        let files = post
+            .get("file")
+            .into_iter()
+            .chain(post["attachments"].as_array().into_iter().flatten());
+        for file in files {
+            let (path, file_name) = match (file["path"].as_str(), file["name"].as_str()) {
+                (Some(path), Some(file_name)) => (path, file_name),
+                _ => continue,
+            };
+            if !hashes.insert(extract_hash(path)) {
+                continue;
+            }
+            let ext = extract_ext(file_name);
+            let (name, kind) = if IMAGE_EXTS.contains(&ext.to_lowercase().as_str()) {
+                pg += 1;
+                (format!("{pg:0>4}"), Kind::Image)
+            } else {
+                let name = sanitize_filename::sanitize(&file_name[..file_name.len() - ext.len()]);
+                (name, Kind::File)
+            };
+            items.push(Item {
+                url: format!("{base_url}/data{path}"),
+                name,
+                ext: ext.to_string(),
+                kind,
+            });
+        }
+
+        // Save the post content as HTML and the metadata as JSON.
+        let metadata = json!({
+            "id": id,
+            "service": post["service"],
+            "user": post["user"],
+            "title": title,
+            "published": post["published"],
+        });
+        let content = post["content"].as_str().unwrap_or_default().trim();
+        let sidecars = vec![("post.html", ".html", content.to_string())];
+        save_post(crawler, &output, &name, id, items, sidecars, &metadata);
+    }
+}
+
+fn base_url(service: &str) -> &'static str {
+    // Services of Coomer are archived separately.
+    match service {
+        "onlyfans" | "fansly" | "candfans" => "https://coomer.su",
+        _ => "https://kemono.su",
+    }
+}
+
+fn extract_hash(path: &str) -> &str {
+    // Paths look like `/ab/cd/<hash>.<ext>`.
+    let name = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+    name.split('.').next().unwrap()
+}
+
+fn extract_ext(name: &str) -> &str {
+    name.rfind('.').map_or("", |i| &name[i..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base_url, extract_ext, extract_hash};
+
+    #[test]
+    fn hash() {
+        assert_eq!(extract_hash("/0a/1b/0a1b2c3d4e5f.png"), "0a1b2c3d4e5f");
+    }
+
+    #[test]
+    fn ext() {
+        assert_eq!(extract_ext("image.PNG"), ".PNG");
+        assert_eq!(extract_ext("README"), "");
+    }
+
+    #[test]
+    fn services() {
+        assert_eq!(base_url("fanbox"), "https://kemono.su");
+        assert_eq!(base_url("onlyfans"), "https://coomer.su");
+    }
+}
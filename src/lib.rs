@@ -1,7 +1,17 @@
+mod booru;
 mod crawler;
+mod dedup;
 mod exhentai;
 mod fanbox;
+mod fantia;
+mod hitomi;
+mod kemono;
+mod metadata;
+mod nhentai;
 mod pixiv;
+mod post;
+mod verify;
+mod x;
 
 use clap::{Parser, Subcommand};
 use crawler::Crawler;
@@ -14,6 +24,9 @@ const TIMEOUT: u64 = 30;
 const RETRY: usize = 1;
 const OUTPUT: &str = ".";
 const RELOAD: usize = 1;
+const PAGES: usize = 1;
+const LIMIT: usize = 100;
+const DEDUP_THRESHOLD: u32 = 4;
 
 #[derive(Parser, Debug)]
 #[clap(version)]
@@ -33,6 +46,12 @@ pub struct Arguments {
     #[clap(long, parse(from_os_str))]
     output: Option<PathBuf>,
 
+    #[clap(long, arg_enum)]
+    dedup: Option<dedup::Mode>,
+
+    #[clap(long)]
+    dedup_threshold: Option<u32>,
+
     #[clap(subcommand)]
     website: Option<Website>,
 }
@@ -40,9 +59,36 @@ pub struct Arguments {
 #[derive(Subcommand, Debug)]
 enum Website {
     Exhentai {
+        #[clap(long, arg_enum)]
+        domain: Option<exhentai::Domain>,
+
         #[clap(long)]
         reload: Option<usize>,
 
+        #[clap(long)]
+        original: bool,
+
+        #[clap(long)]
+        quota_wait: bool,
+
+        #[clap(long, arg_enum)]
+        archive: Option<exhentai::Archive>,
+
+        #[clap(long)]
+        extract: bool,
+
+        #[clap(long, arg_enum)]
+        torrent: Option<exhentai::Torrent>,
+
+        #[clap(long)]
+        mpv: bool,
+
+        #[clap(long)]
+        skip_expunged: bool,
+
+        #[clap(long)]
+        exclude_tag: Vec<String>,
+
         #[clap(long)]
         ipb_member_id: Option<String>,
 
@@ -50,11 +96,17 @@ enum Website {
         ipb_pass_hash: Option<String>,
 
         galleries: Vec<String>,
+
+        #[clap(subcommand)]
+        target: Option<ExhentaiTarget>,
     },
     Pixiv {
         #[clap(long)]
         phpsessid: Option<String>,
 
+        #[clap(long)]
+        embed_metadata: bool,
+
         #[clap(subcommand)]
         target: Option<PixivTarget>,
     },
@@ -65,7 +117,100 @@ enum Website {
         #[clap(long)]
         cf_clearance: Option<String>,
 
+        #[clap(long)]
+        download_links: bool,
+
         posts: Vec<String>,
+
+        #[clap(subcommand)]
+        target: Option<FanboxTarget>,
+    },
+    Fantia {
+        #[clap(long)]
+        session_id: Option<String>,
+
+        #[clap(subcommand)]
+        target: Option<FantiaTarget>,
+    },
+    Nhentai {
+        galleries: Vec<String>,
+    },
+    Hitomi {
+        galleries: Vec<String>,
+    },
+    Kemono {
+        targets: Vec<String>,
+    },
+    X {
+        #[clap(long)]
+        auth_token: Option<String>,
+
+        #[clap(long)]
+        ct0: Option<String>,
+
+        #[clap(subcommand)]
+        target: Option<XTarget>,
+    },
+    Verify {
+        #[clap(parse(from_os_str))]
+        directory: PathBuf,
+
+        #[clap(long)]
+        repair: bool,
+    },
+    Booru {
+        #[clap(arg_enum)]
+        site: booru::Site,
+
+        tags: String,
+
+        #[clap(long)]
+        pages: Option<usize>,
+
+        #[clap(long)]
+        limit: Option<usize>,
+
+        #[clap(long)]
+        user: Option<String>,
+
+        #[clap(long)]
+        key: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ExhentaiTarget {
+    Gallery {
+        galleries: Vec<String>,
+    },
+    Search {
+        query: String,
+
+        #[clap(long)]
+        category: Vec<String>,
+
+        #[clap(long, possible_values = &["2", "3", "4", "5"])]
+        min_rating: Option<usize>,
+
+        #[clap(long, default_value_t = 1)]
+        pages: usize,
+
+        #[clap(long)]
+        list: bool,
+    },
+    Favorites {
+        #[clap(long)]
+        category: Option<usize>,
+
+        #[clap(long)]
+        list: bool,
+    },
+    Update {
+        #[clap(parse(from_os_str))]
+        directory: PathBuf,
+
+        #[clap(long)]
+        reuse: bool,
     },
 }
 
@@ -73,6 +218,33 @@ enum Website {
 enum PixivTarget {
     User { users: Vec<String> },
     Illust { illusts: Vec<String> },
+    Series { series: Vec<String> },
+}
+
+#[derive(Subcommand, Debug)]
+enum FanboxTarget {
+    Post {
+        posts: Vec<String>,
+    },
+    Creator {
+        creators: Vec<String>,
+    },
+    Supporting,
+    Feed {
+        #[clap(long, parse(try_from_str = fanbox::parse_date))]
+        since: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum FantiaTarget {
+    Post { posts: Vec<String> },
+    Fanclub { fanclubs: Vec<String> },
+}
+
+#[derive(Subcommand, Debug)]
+enum XTarget {
+    User { users: Vec<String> },
 }
 
 #[derive(Deserialize, Default, Debug)]
@@ -81,14 +253,28 @@ pub struct Config {
     timeout: Option<u64>,
     retry: Option<usize>,
     output: Option<PathBuf>,
+    dedup: Option<dedup::Mode>,
+    dedup_threshold: Option<u32>,
     exhentai: Option<ExhentaiConfig>,
     pixiv: Option<PixivConfig>,
     fanbox: Option<FanboxConfig>,
+    fantia: Option<FantiaConfig>,
+    x: Option<XConfig>,
+    booru: Option<BooruConfig>,
 }
 
 #[derive(Deserialize, Debug)]
 struct ExhentaiConfig {
+    domain: Option<exhentai::Domain>,
     reload: Option<usize>,
+    original: Option<bool>,
+    quota_wait: Option<bool>,
+    archive: Option<exhentai::Archive>,
+    extract: Option<bool>,
+    torrent: Option<exhentai::Torrent>,
+    mpv: Option<bool>,
+    skip_expunged: Option<bool>,
+    exclude_tags: Option<Vec<String>>,
     ipb_member_id: Option<String>,
     ipb_pass_hash: Option<String>,
 }
@@ -96,12 +282,41 @@ struct ExhentaiConfig {
 #[derive(Deserialize, Debug)]
 struct PixivConfig {
     phpsessid: Option<String>,
+    embed_metadata: Option<bool>,
 }
 
 #[derive(Deserialize, Debug)]
 struct FanboxConfig {
     fanboxsessid: Option<String>,
     cf_clearance: Option<String>,
+    download_links: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FantiaConfig {
+    session_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct XConfig {
+    auth_token: Option<String>,
+    ct0: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BooruConfig {
+    pages: Option<usize>,
+    limit: Option<usize>,
+    danbooru: Option<BooruCredential>,
+    gelbooru: Option<BooruCredential>,
+    yandere: Option<BooruCredential>,
+    konachan: Option<BooruCredential>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BooruCredential {
+    user: Option<String>,
+    key: Option<String>,
 }
 
 pub fn run(arguments: Arguments, config: Config) {
@@ -118,43 +333,165 @@ pub fn run(arguments: Arguments, config: Config) {
         .output
         .or(config.output)
         .unwrap_or_else(|| Path::new(OUTPUT).to_path_buf());
+    let dedup = arguments.dedup.or(config.dedup);
+    let dedup_threshold = arguments
+        .dedup_threshold
+        .or(config.dedup_threshold)
+        .unwrap_or(DEDUP_THRESHOLD);
+    let dedup_output = output.clone();
     match arguments.website {
         Some(Website::Exhentai {
+            domain,
             reload,
+            original,
+            quota_wait,
+            archive,
+            extract,
+            torrent,
+            mpv,
+            skip_expunged,
+            exclude_tag,
             ipb_member_id,
             ipb_pass_hash,
             galleries,
+            target,
         }) => {
             let reload = reload
                 .or_else(|| config.exhentai.as_ref().and_then(|eh| eh.reload))
                 .unwrap_or(RELOAD);
-            let ipb_member_id = ipb_member_id
-                .or_else(|| {
-                    config
-                        .exhentai
-                        .as_ref()
-                        .and_then(|eh| eh.ipb_member_id.clone())
-                })
-                .expect("`ipb_member_id` is not defined");
-            let ipb_pass_hash = ipb_pass_hash
-                .or_else(|| {
-                    config
-                        .exhentai
-                        .as_ref()
-                        .and_then(|eh| eh.ipb_pass_hash.clone())
-                })
-                .expect("`ipb_pass_hash` is not defined");
-            let cookies = vec![
-                ("ipb_member_id", ipb_member_id.as_str()),
-                ("ipb_pass_hash", ipb_pass_hash.as_str()),
-            ];
+            let original = original
+                || config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.original)
+                    .unwrap_or(false);
+            let quota_wait = quota_wait
+                || config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.quota_wait)
+                    .unwrap_or(false);
+            let archive = archive.or_else(|| config.exhentai.as_ref().and_then(|eh| eh.archive));
+            let extract = extract
+                || config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.extract)
+                    .unwrap_or(false);
+            let torrent = torrent.or_else(|| config.exhentai.as_ref().and_then(|eh| eh.torrent));
+            let mpv = mpv
+                || config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.mpv)
+                    .unwrap_or(false);
+            let skip_expunged = skip_expunged
+                || config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.skip_expunged)
+                    .unwrap_or(false);
+            let exclude_tags = if exclude_tag.is_empty() {
+                config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.exclude_tags.clone())
+                    .unwrap_or_default()
+            } else {
+                exclude_tag
+            };
+            let ipb_member_id = ipb_member_id.or_else(|| {
+                config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.ipb_member_id.clone())
+            });
+            let ipb_pass_hash = ipb_pass_hash.or_else(|| {
+                config
+                    .exhentai
+                    .as_ref()
+                    .and_then(|eh| eh.ipb_pass_hash.clone())
+            });
+
+            // Cookies are only required by ExHentai, which is the default domain
+            // if they are defined.
+            let domain = domain
+                .or_else(|| config.exhentai.as_ref().and_then(|eh| eh.domain))
+                .unwrap_or(match (&ipb_member_id, &ipb_pass_hash) {
+                    (Some(_), Some(_)) => exhentai::Domain::Exhentai,
+                    _ => exhentai::Domain::EHentai,
+                });
+            let mut cookies = Vec::new();
+            match &ipb_member_id {
+                Some(ipb_member_id) => cookies.push(("ipb_member_id", ipb_member_id.as_str())),
+                None if domain == exhentai::Domain::Exhentai => {
+                    panic!("`ipb_member_id` is not defined")
+                }
+                None => (),
+            }
+            match &ipb_pass_hash {
+                Some(ipb_pass_hash) => cookies.push(("ipb_pass_hash", ipb_pass_hash.as_str())),
+                None if domain == exhentai::Domain::Exhentai => {
+                    panic!("`ipb_pass_hash` is not defined")
+                }
+                None => (),
+            }
+            let logged_in = cookies.len() == 2;
             let crawler = Crawler::new(concurrency, timeout, Vec::new(), cookies, retry);
-            exhentai::crawl_galleries(&crawler, output, reload, galleries);
+            let options = exhentai::Options {
+                domain,
+                reload,
+                original,
+                quota_wait,
+                archive,
+                extract,
+                torrent,
+                mpv,
+                skip_expunged,
+                exclude_tags,
+                logged_in,
+            };
+            match target {
+                Some(ExhentaiTarget::Gallery { galleries }) => {
+                    exhentai::crawl_galleries(&crawler, output, &options, galleries)
+                }
+                Some(ExhentaiTarget::Search {
+                    query,
+                    category,
+                    min_rating,
+                    pages,
+                    list,
+                }) => {
+                    let search = exhentai::Search {
+                        query,
+                        categories: category,
+                        min_rating,
+                        pages,
+                    };
+                    exhentai::crawl_search(&crawler, output, &options, search, list)
+                }
+                Some(ExhentaiTarget::Favorites { category, list }) => {
+                    exhentai::crawl_favorites(&crawler, output, &options, category, list)
+                }
+                Some(ExhentaiTarget::Update { directory, reuse }) => {
+                    exhentai::update_galleries(&crawler, directory, &options, reuse)
+                }
+                None => exhentai::crawl_galleries(&crawler, output, &options, galleries),
+            }
         }
-        Some(Website::Pixiv { phpsessid, target }) => {
+        Some(Website::Pixiv {
+            phpsessid,
+            embed_metadata,
+            target,
+        }) => {
             let phpsessid = phpsessid
                 .or_else(|| config.pixiv.as_ref().and_then(|px| px.phpsessid.clone()))
                 .expect("`phpsessid` is not defined");
+            let embed_metadata = embed_metadata
+                || config
+                    .pixiv
+                    .and_then(|px| px.embed_metadata)
+                    .unwrap_or(false);
             let crawler = Crawler::new(
                 concurrency,
                 timeout,
@@ -163,9 +500,14 @@ pub fn run(arguments: Arguments, config: Config) {
                 retry,
             );
             match target {
-                Some(PixivTarget::User { users }) => pixiv::crawl_users(&crawler, output, users),
+                Some(PixivTarget::User { users }) => {
+                    pixiv::crawl_users(&crawler, output, embed_metadata, users)
+                }
                 Some(PixivTarget::Illust { illusts }) => {
-                    pixiv::crawl_illusts(&crawler, output, illusts)
+                    pixiv::crawl_illusts(&crawler, output, embed_metadata, illusts)
+                }
+                Some(PixivTarget::Series { series }) => {
+                    pixiv::crawl_series(&crawler, output, embed_metadata, series)
                 }
                 None => (),
             }
@@ -173,7 +515,9 @@ pub fn run(arguments: Arguments, config: Config) {
         Some(Website::Fanbox {
             fanboxsessid,
             cf_clearance,
+            download_links,
             posts,
+            target,
         }) => {
             let fanboxsessid = fanboxsessid
                 .or_else(|| {
@@ -184,8 +528,18 @@ pub fn run(arguments: Arguments, config: Config) {
                 })
                 .expect("`FANBOXSESSID` is not defined");
             let cf_clearance = cf_clearance
-                .or_else(|| config.fanbox.and_then(|fb| fb.cf_clearance))
+                .or_else(|| {
+                    config
+                        .fanbox
+                        .as_ref()
+                        .and_then(|fb| fb.cf_clearance.clone())
+                })
                 .expect("`cf_clearance` is not defined");
+            let download_links = download_links
+                || config
+                    .fanbox
+                    .and_then(|fb| fb.download_links)
+                    .unwrap_or(false);
             let crawler = Crawler::new(
                 concurrency,
                 timeout,
@@ -196,8 +550,195 @@ pub fn run(arguments: Arguments, config: Config) {
                 ],
                 retry,
             );
-            fanbox::crawl_posts(&crawler, output, posts);
+            match target {
+                Some(FanboxTarget::Post { posts }) => {
+                    fanbox::crawl_posts(&crawler, output, download_links, posts)
+                }
+                Some(FanboxTarget::Creator { creators }) => {
+                    fanbox::crawl_creators(&crawler, output, download_links, creators)
+                }
+                Some(FanboxTarget::Supporting) => {
+                    fanbox::crawl_supporting(&crawler, output, download_links)
+                }
+                Some(FanboxTarget::Feed { since }) => {
+                    fanbox::crawl_feed(&crawler, output, download_links, since)
+                }
+                None => fanbox::crawl_posts(&crawler, output, download_links, posts),
+            }
+        }
+        Some(Website::Fantia { session_id, target }) => {
+            let session_id = session_id
+                .or_else(|| config.fantia.as_ref().and_then(|ft| ft.session_id.clone()))
+                .expect("`_session_id` is not defined");
+            let crawler = Crawler::new(
+                concurrency,
+                timeout,
+                vec![("X-Requested-With", "XMLHttpRequest")],
+                vec![("_session_id", &session_id)],
+                retry,
+            );
+            match target {
+                Some(FantiaTarget::Post { posts }) => fantia::crawl_posts(&crawler, output, posts),
+                Some(FantiaTarget::Fanclub { fanclubs }) => {
+                    fantia::crawl_fanclubs(&crawler, output, fanclubs)
+                }
+                None => (),
+            }
+        }
+        Some(Website::Nhentai { galleries }) => {
+            let crawler = Crawler::new(concurrency, timeout, Vec::new(), Vec::new(), retry);
+            nhentai::crawl_galleries(&crawler, output, galleries);
+        }
+        Some(Website::Hitomi { galleries }) => {
+            let crawler = Crawler::new(
+                concurrency,
+                timeout,
+                vec![("Referer", "https://hitomi.la/")],
+                Vec::new(),
+                retry,
+            );
+            hitomi::crawl_galleries(&crawler, output, galleries);
+        }
+        Some(Website::Kemono { targets }) => {
+            let crawler = Crawler::new(concurrency, timeout, Vec::new(), Vec::new(), retry);
+            kemono::crawl_targets(&crawler, output, targets);
+        }
+        Some(Website::X {
+            auth_token,
+            ct0,
+            target,
+        }) => {
+            let auth_token = auth_token
+                .or_else(|| config.x.as_ref().and_then(|x| x.auth_token.clone()))
+                .expect("`auth_token` is not defined");
+            let ct0 = ct0
+                .or_else(|| config.x.as_ref().and_then(|x| x.ct0.clone()))
+                .expect("`ct0` is not defined");
+            let crawler = Crawler::new(
+                concurrency,
+                timeout,
+                vec![("Authorization", x::BEARER_TOKEN), ("X-Csrf-Token", &ct0)],
+                vec![("auth_token", &auth_token), ("ct0", &ct0)],
+                retry,
+            );
+            match target {
+                Some(XTarget::User { users }) => x::crawl_users(&crawler, output, users),
+                None => (),
+            }
+        }
+        Some(Website::Verify { directory, repair }) => {
+            let repair = if repair {
+                // Galleries are repaired with the ExHentai config as they are
+                // crawled, except for archives, torrents and filters.
+                let exhentai = config.exhentai.as_ref().and_then(|eh| {
+                    let domain =
+                        eh.domain
+                            .unwrap_or(match (&eh.ipb_member_id, &eh.ipb_pass_hash) {
+                                (Some(_), Some(_)) => exhentai::Domain::Exhentai,
+                                _ => exhentai::Domain::EHentai,
+                            });
+                    let cookies = match (&eh.ipb_member_id, &eh.ipb_pass_hash) {
+                        (Some(ipb_member_id), Some(ipb_pass_hash)) => vec![
+                            ("ipb_member_id", ipb_member_id.as_str()),
+                            ("ipb_pass_hash", ipb_pass_hash.as_str()),
+                        ],
+                        _ if domain == exhentai::Domain::Exhentai => return None,
+                        _ => Vec::new(),
+                    };
+                    let logged_in = !cookies.is_empty();
+                    let crawler = Crawler::new(concurrency, timeout, Vec::new(), cookies, retry);
+                    let options = exhentai::Options {
+                        domain,
+                        reload: eh.reload.unwrap_or(RELOAD),
+                        original: eh.original.unwrap_or(false),
+                        quota_wait: eh.quota_wait.unwrap_or(false),
+                        archive: None,
+                        extract: false,
+                        torrent: None,
+                        mpv: eh.mpv.unwrap_or(false),
+                        skip_expunged: false,
+                        exclude_tags: Vec::new(),
+                        logged_in,
+                    };
+                    Some((crawler, options))
+                });
+
+                // Posts are repaired with the cookies of their sites.
+                let fanbox = config.fanbox.as_ref().and_then(|fb| {
+                    let fanboxsessid = fb.fanboxsessid.as_ref()?;
+                    let cf_clearance = fb.cf_clearance.as_ref()?;
+                    Some(Crawler::new(
+                        concurrency,
+                        timeout,
+                        vec![("Origin", "https://www.fanbox.cc")],
+                        vec![
+                            ("FANBOXSESSID", fanboxsessid),
+                            ("cf_clearance", cf_clearance),
+                        ],
+                        retry,
+                    ))
+                });
+                let fantia = config.fantia.as_ref().and_then(|ft| {
+                    Some(Crawler::new(
+                        concurrency,
+                        timeout,
+                        vec![("X-Requested-With", "XMLHttpRequest")],
+                        vec![("_session_id", ft.session_id.as_ref()?)],
+                        retry,
+                    ))
+                });
+                Some(verify::Repair {
+                    crawler: Crawler::new(concurrency, timeout, Vec::new(), Vec::new(), retry),
+                    hitomi: Crawler::new(
+                        concurrency,
+                        timeout,
+                        vec![("Referer", "https://hitomi.la/")],
+                        Vec::new(),
+                        retry,
+                    ),
+                    exhentai,
+                    fanbox,
+                    fantia,
+                })
+            } else {
+                None
+            };
+            verify::verify_tree(&directory, repair.as_ref());
+        }
+        Some(Website::Booru {
+            site,
+            tags,
+            pages,
+            limit,
+            user,
+            key,
+        }) => {
+            let pages = pages
+                .or_else(|| config.booru.as_ref().and_then(|br| br.pages))
+                .unwrap_or(PAGES);
+            let limit = limit
+                .or_else(|| config.booru.as_ref().and_then(|br| br.limit))
+                .unwrap_or(LIMIT);
+            let credential = config.booru.as_ref().and_then(|br| match site {
+                booru::Site::Danbooru => br.danbooru.as_ref(),
+                booru::Site::Gelbooru => br.gelbooru.as_ref(),
+                booru::Site::Yandere => br.yandere.as_ref(),
+                booru::Site::Konachan => br.konachan.as_ref(),
+            });
+            let user = user.or_else(|| credential.and_then(|c| c.user.clone()));
+            let key = key.or_else(|| credential.and_then(|c| c.key.clone()));
+            let credential = match (user, key) {
+                (Some(user), Some(key)) => Some(booru::Credential { user, key }),
+                _ => None,
+            };
+            let crawler = Crawler::new(concurrency, timeout, Vec::new(), Vec::new(), retry);
+            booru::crawl_posts(&crawler, output, site, credential, tags, pages, limit);
         }
         None => {}
     }
+
+    // Find near-duplicates of downloaded images across the whole output directory.
+    if let Some(mode) = dedup {
+        dedup::dedup_tree(&dedup_output, mode, dedup_threshold);
+    }
 }
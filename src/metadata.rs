@@ -0,0 +1,237 @@
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const JPEG_APP0: [u8; 2] = [0xFF, 0xE0];
+const JPEG_APP1: [u8; 2] = [0xFF, 0xE1];
+const JPEG_APP13: [u8; 2] = [0xFF, 0xED];
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const SEGMENT_LIMIT: usize = 65533;
+
+pub struct Metadata {
+    pub title: String,
+    pub artist: String,
+    pub tags: Vec<String>,
+    pub description: String,
+    pub url: String,
+    pub date: String,
+}
+
+pub fn embed(image: Vec<u8>, metadata: &Metadata) -> Vec<u8> {
+    // Images in formats other than JPEG and PNG are returned as they are.
+    if image.starts_with(&JPEG_SOI) {
+        embed_jpeg(image, metadata)
+    } else if image.starts_with(&PNG_SIGNATURE) {
+        embed_png(image, metadata)
+    } else {
+        image
+    }
+}
+
+fn embed_jpeg(image: Vec<u8>, metadata: &Metadata) -> Vec<u8> {
+    // Segments are inserted after the JFIF header if there is one.
+    let mut pos = JPEG_SOI.len();
+    if image[pos..].starts_with(&JPEG_APP0) && image.len() > pos + 4 {
+        pos += 2 + u16::from_be_bytes([image[pos + 2], image[pos + 3]]) as usize;
+    }
+
+    let mut segments = Vec::new();
+    let mut xmp = XMP_NAMESPACE.to_vec();
+    xmp.extend(build_xmp(metadata).as_bytes());
+    if xmp.len() <= SEGMENT_LIMIT {
+        segments.extend(JPEG_APP1);
+        segments.extend((xmp.len() as u16 + 2).to_be_bytes());
+        segments.extend(xmp);
+    }
+    let irb = build_photoshop_irb(&build_iptc(metadata));
+    if irb.len() <= SEGMENT_LIMIT {
+        segments.extend(JPEG_APP13);
+        segments.extend((irb.len() as u16 + 2).to_be_bytes());
+        segments.extend(irb);
+    }
+
+    let mut embedded = Vec::with_capacity(image.len() + segments.len());
+    embedded.extend(&image[..pos]);
+    embedded.extend(segments);
+    embedded.extend(&image[pos..]);
+    embedded
+}
+
+fn embed_png(image: Vec<u8>, metadata: &Metadata) -> Vec<u8> {
+    // The iTXt chunk is inserted right after IHDR, which is always the first.
+    let ihdr = PNG_SIGNATURE.len();
+    if image.len() < ihdr + 4 {
+        return image;
+    }
+    let ihdr_len = u32::from_be_bytes(image[ihdr..ihdr + 4].try_into().unwrap()) as usize;
+    let pos = ihdr + 12 + ihdr_len;
+    if image.len() < pos {
+        return image;
+    }
+
+    // Keyword, compression flag, compression method, language and translated
+    // keyword are followed by the uncompressed XMP packet.
+    let mut data = XMP_KEYWORD.to_vec();
+    data.extend([0, 0, 0, 0, 0]);
+    data.extend(build_xmp(metadata).as_bytes());
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend((data.len() as u32).to_be_bytes());
+    chunk.extend(b"iTXt");
+    chunk.extend(&data);
+    chunk.extend(crc32fast::hash(&chunk[4..]).to_be_bytes());
+
+    let mut embedded = Vec::with_capacity(image.len() + chunk.len());
+    embedded.extend(&image[..pos]);
+    embedded.extend(chunk);
+    embedded.extend(&image[pos..]);
+    embedded
+}
+
+fn build_xmp(metadata: &Metadata) -> String {
+    let tags: String = metadata
+        .tags
+        .iter()
+        .map(|tag| format!("<rdf:li>{}</rdf:li>", escape(tag)))
+        .collect();
+    format!(
+        concat!(
+            r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>"#,
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+            r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+            r#"<rdf:Description rdf:about="""#,
+            r#" xmlns:dc="http://purl.org/dc/elements/1.1/""#,
+            r#" xmlns:xmp="http://ns.adobe.com/xap/1.0/""#,
+            r#" xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/">"#,
+            r#"<dc:title><rdf:Alt><rdf:li xml:lang="x-default">{}</rdf:li></rdf:Alt></dc:title>"#,
+            r#"<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>"#,
+            r#"<dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>"#,
+            r#"<dc:description><rdf:Alt><rdf:li xml:lang="x-default">{}</rdf:li></rdf:Alt></dc:description>"#,
+            r#"<dc:source>{}</dc:source>"#,
+            r#"<xmp:CreateDate>{}</xmp:CreateDate>"#,
+            r#"<photoshop:DateCreated>{}</photoshop:DateCreated>"#,
+            r#"</rdf:Description></rdf:RDF></x:xmpmeta>"#,
+            r#"<?xpacket end="w"?>"#,
+        ),
+        escape(&metadata.title),
+        escape(&metadata.artist),
+        tags,
+        escape(&metadata.description),
+        escape(&metadata.url),
+        escape(&metadata.date),
+        escape(&metadata.date),
+    )
+}
+
+fn build_iptc(metadata: &Metadata) -> Vec<u8> {
+    let mut datasets = vec![
+        // Mark the character set as UTF-8.
+        (1, 90, b"\x1B%G".to_vec()),
+        (2, 0, vec![0, 4]),
+        (2, 5, metadata.title.as_bytes().to_vec()),
+        (2, 80, metadata.artist.as_bytes().to_vec()),
+        (2, 120, metadata.description.as_bytes().to_vec()),
+    ];
+    datasets.extend(
+        metadata
+            .tags
+            .iter()
+            .map(|tag| (2, 25, tag.as_bytes().to_vec())),
+    );
+    let date: String = metadata
+        .date
+        .chars()
+        .filter(char::is_ascii_digit)
+        .take(8)
+        .collect();
+    if date.len() == 8 {
+        datasets.push((2, 55, date.into_bytes()));
+    }
+
+    let mut iptc = Vec::new();
+    for (record, dataset, data) in datasets {
+        // Standard datasets are limited to 32767 bytes.
+        if data.is_empty() || data.len() > 0x7FFF {
+            continue;
+        }
+        iptc.extend([0x1C, record, dataset]);
+        iptc.extend((data.len() as u16).to_be_bytes());
+        iptc.extend(data);
+    }
+    iptc
+}
+
+fn build_photoshop_irb(iptc: &[u8]) -> Vec<u8> {
+    // The IPTC block is stored as the 0x0404 resource with an empty name.
+    let mut irb = b"Photoshop 3.0\0".to_vec();
+    irb.extend(b"8BIM");
+    irb.extend(0x0404u16.to_be_bytes());
+    irb.extend([0, 0]);
+    irb.extend((iptc.len() as u32).to_be_bytes());
+    irb.extend(iptc);
+    if iptc.len() % 2 == 1 {
+        irb.push(0);
+    }
+    irb
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_iptc, embed, Metadata};
+
+    fn metadata() -> Metadata {
+        Metadata {
+            title: String::from("Title & Co"),
+            artist: String::from("Artist"),
+            tags: vec![String::from("tag1"), String::from("タグ")],
+            description: String::new(),
+            url: String::from("https://www.pixiv.net/artworks/1"),
+            date: String::from("2024-01-06T10:00:00+00:00"),
+        }
+    }
+
+    #[test]
+    fn jpeg() {
+        let image = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xD9];
+        let embedded = embed(image, &metadata());
+        assert_eq!(&embedded[..6], &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04]);
+        assert_eq!(&embedded[8..10], &[0xFF, 0xE1]);
+        let xmp = String::from_utf8_lossy(&embedded);
+        assert!(xmp.contains("<rdf:li>tag1</rdf:li><rdf:li>タグ</rdf:li>"));
+        assert!(xmp.contains("Title &amp; Co"));
+        assert!(xmp.contains("Photoshop 3.0"));
+        assert!(embedded.ends_with(&[0xFF, 0xD9]));
+    }
+
+    #[test]
+    fn png() {
+        let mut image = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        image.extend([0, 0, 0, 13]);
+        image.extend(b"IHDR");
+        image.extend([0; 17]);
+        image.extend([0, 0, 0, 0]);
+        image.extend(b"IEND");
+        image.extend([0xAE, 0x42, 0x60, 0x82]);
+        let embedded = embed(image, &metadata());
+        assert_eq!(&embedded[37..41], b"iTXt");
+        assert_eq!(&embedded[41..58], b"XML:com.adobe.xmp");
+        let len = u32::from_be_bytes(embedded[33..37].try_into().unwrap()) as usize;
+        let crc = crc32fast::hash(&embedded[37..41 + len]).to_be_bytes();
+        assert_eq!(&embedded[41 + len..45 + len], &crc);
+        assert!(embedded.ends_with(b"IEND\xAE\x42\x60\x82"));
+    }
+
+    #[test]
+    fn iptc() {
+        let iptc = build_iptc(&metadata());
+        assert!(iptc
+            .windows(7)
+            .any(|w| w == [0x1C, 2, 55, 0, 8, b'2', b'0']));
+        assert!(iptc.windows(9).any(|w| w == b"\x1C\x02\x19\x00\x04tag1"));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::crawler::{name_directory, Crawler};
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+pub fn crawl_galleries(crawler: &Crawler, output: PathBuf, galleries: Vec<String>) {
+    for gallery in galleries {
+        // Process the gallery id and the range.
+        let parts: Vec<_> = gallery.split('/').collect();
+        let (id, range) = match parts[..] {
+            [id] => (id, None),
+            [id, range] => (id, Some(range)),
+            _ => {
+                println!("Invalid Gallery {gallery}");
+                continue;
+            }
+        };
+
+        // Crawl the gallery info.
+        let info_result = crawler
+            .get_json(
+                "",
+                vec![(&format!("https://nhentai.net/api/gallery/{id}"), Vec::new())],
+            )
+            .pop()
+            .unwrap();
+        let info = match info_result {
+            Ok(info) => info,
+            Err(err) => {
+                println!("Fail to crawl the info json for Gallery {id}: {err}");
+                continue;
+            }
+        };
+
+        // Build image urls from the media id and page types.
+        let title = extract_title(&info);
+        let media_id = info["media_id"].as_str().unwrap();
+        let mut image_urls: Vec<_> = info["images"]["pages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(i, page)| {
+                let ext = match page["t"].as_str().unwrap() {
+                    "p" => "png",
+                    "g" => "gif",
+                    "w" => "webp",
+                    _ => "jpg",
+                };
+                format!("https://i.nhentai.net/galleries/{media_id}/{}.{ext}", i + 1)
+            })
+            .collect();
+
+        // Determine a proper range.
+        let (start, end) = if let Some(range) = range {
+            let parts: Vec<_> = range.split('-').collect();
+            if parts.len() != 2 {
+                println!("Invalid range for Gallery {gallery}");
+                continue;
+            }
+            let start = parts[0].parse().unwrap();
+            let end = parts[1].parse().unwrap();
+            (start, end)
+        } else {
+            (1, image_urls.len())
+        };
+        let image_urls: Vec<_> = image_urls.drain(start - 1..end).collect();
+
+        // Create the gallery directory.
+        let directory = match name_directory(&output, &title, id) {
+            Some(directory) => directory,
+            None => {
+                println!("Skip Gallery {id}, which has been downloaded");
+                continue;
+            }
+        };
+        let mut directory_path = output.clone();
+        directory_path.push(&directory);
+        fs::create_dir(&directory_path).unwrap();
+
+        // Save the gallery info with the downloaded range as metadata.
+        let mut info = info;
+        info["range"] = json!([start, end]);
+        let file = File::create(directory_path.join("metadata.json")).unwrap();
+        serde_json::to_writer_pretty(file, &info).unwrap();
+
+        // Crawl images in this gallery.
+        let image_requests = image_urls
+            .iter()
+            .map(|url| (url.as_str(), Vec::new(), None))
+            .collect();
+        let image_results = crawler.get_image(&title, image_requests);
+
+        // Write images to local files, numbered from the start of the range as
+        // ExHentai galleries are.
+        for ((i, url), result) in image_urls.iter().enumerate().zip(image_results) {
+            let pg = start + i;
+            let image = match result {
+                Ok(image) => image,
+                Err(err) => {
+                    println!("Fail to crawl page {pg} for Gallery {id}: {err}");
+                    continue;
+                }
+            };
+            let ext = &url[url.rfind('.').unwrap()..];
+            let mut path = directory_path.clone();
+            path.push(format!("{:0>4}{ext}", i + 1));
+            let mut file = File::create(path).unwrap();
+            file.write_all(&image).unwrap();
+        }
+    }
+}
+
+fn extract_title(info: &Value) -> String {
+    // Prefer the Japanese title as ExHentai does.
+    ["japanese", "pretty", "english"]
+        .iter()
+        .filter_map(|key| info["title"][key].as_str())
+        .find(|title| !title.is_empty())
+        .unwrap_or_default()
+        .to_string()
+}
@@ -1,4 +1,5 @@
 use crate::crawler::Crawler;
+use crate::metadata::{self, Metadata};
 use kuchiki::traits::*;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -7,7 +8,7 @@ use std::fs::{self, File};
 use std::io::Write;
 use std::path::PathBuf;
 
-pub fn crawl_users(crawler: &Crawler, output: PathBuf, users: Vec<String>) {
+pub fn crawl_users(crawler: &Crawler, output: PathBuf, embed_metadata: bool, users: Vec<String>) {
     for user in users {
         // Process the user id and the range.
         let parts: Vec<_> = user.split('/').collect();
@@ -97,12 +98,96 @@ pub fn crawl_users(crawler: &Crawler, output: PathBuf, users: Vec<String>) {
         crawl_illusts(
             crawler,
             directory_path,
+            embed_metadata,
             illusts.drain(start - 1..end).collect(),
         );
     }
 }
 
-pub fn crawl_illusts(crawler: &Crawler, output: PathBuf, illusts: Vec<String>) {
+pub fn crawl_series(crawler: &Crawler, output: PathBuf, embed_metadata: bool, series: Vec<String>) {
+    for id in series {
+        // Crawl the series index page by page.
+        let mut title = String::new();
+        let mut user = String::new();
+        let mut chapters = Vec::new();
+        let mut page = 1;
+        loop {
+            let url = format!("https://www.pixiv.net/ajax/series/{id}");
+            let pg = page.to_string();
+            let index_result = crawler
+                .get_json("", vec![(&url, vec![("p", &pg)])])
+                .pop()
+                .unwrap();
+            let json = match index_result {
+                Ok(json) => json,
+                Err(err) => {
+                    println!("Fail to crawl index page {page} for Series {id}: {err}");
+                    break;
+                }
+            };
+            if page == 1 {
+                // The index also lists other series and users it refers to, so
+                // look up the requested series and its author by id.
+                let body = &json["body"];
+                let series = body["illustSeries"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find(|series| series["id"].as_str() == Some(id.as_str()))
+                    .unwrap();
+                title = series["title"].as_str().unwrap().to_string();
+                let user_id = series["userId"].as_str().unwrap();
+                user = body["users"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find(|user| user["userId"].as_str() == Some(user_id))
+                    .unwrap()["name"]
+                    .as_str()
+                    .unwrap()
+                    .to_string();
+            }
+            let works = json["body"]["page"]["series"].as_array().unwrap();
+            chapters.extend(works.iter().map(|work| {
+                (
+                    work["order"].as_u64().unwrap(),
+                    work["workId"].as_str().unwrap().to_string(),
+                )
+            }));
+            let total = json["body"]["page"]["total"].as_u64().unwrap() as usize;
+            if works.is_empty() || chapters.len() >= total {
+                break;
+            }
+            page += 1;
+        }
+        if chapters.is_empty() {
+            continue;
+        }
+
+        // Create the series directory.
+        let mut directory_path = output.clone();
+        let directory = sanitize_filename::sanitize(format!("[{user}] {title}"));
+        directory_path.push(&directory);
+        fs::create_dir(&directory_path).unwrap();
+
+        // Crawl chapters in the series order.
+        chapters.sort_unstable_by_key(|(order, _)| *order);
+        println!("{title} - {} Chapters", chapters.len());
+        for (order, illust) in chapters {
+            let mut chapter_path = directory_path.clone();
+            chapter_path.push(format!("{order:0>4}"));
+            fs::create_dir(&chapter_path).unwrap();
+            crawl_illusts(crawler, chapter_path, embed_metadata, vec![illust]);
+        }
+    }
+}
+
+pub fn crawl_illusts(
+    crawler: &Crawler,
+    output: PathBuf,
+    embed_metadata: bool,
+    illusts: Vec<String>,
+) {
     // Crawl illust pages.
     let page_urls: Vec<_> = illusts
         .iter()
@@ -161,6 +246,22 @@ pub fn crawl_illusts(crawler: &Crawler, output: PathBuf, illusts: Vec<String>) {
             format!("{}{}{}", &caps[1], &caps[2], &caps[3])
         };
         let title = json["body"]["title"].as_str().unwrap();
+        let metadata = Metadata {
+            title: title.to_string(),
+            artist: user.to_string(),
+            tags: json["body"]["tags"]["tags"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|tag| tag["tag"].as_str().map(String::from))
+                .collect(),
+            description: json["body"]["illustComment"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            url: format!("https://www.pixiv.net/artworks/{id}"),
+            date: json["body"]["createDate"].as_str().unwrap().to_string(),
+        };
 
         // Create the illust directory if necessary.
         let image_urls: Vec<_> = index["body"]
@@ -177,8 +278,11 @@ pub fn crawl_illusts(crawler: &Crawler, output: PathBuf, illusts: Vec<String>) {
         };
 
         // Crawl images in this illust.
-        let image_requests = image_urls.iter().map(|url| (*url, Vec::new())).collect();
-        let image_results = crawler.get_byte(&illust_name, image_requests);
+        let image_requests = image_urls
+            .iter()
+            .map(|url| (*url, Vec::new(), None))
+            .collect();
+        let image_results = crawler.get_image(&illust_name, image_requests);
 
         // Write images to local files.
         for ((i, url), result) in image_urls.iter().enumerate().zip(image_results) {
@@ -197,6 +301,11 @@ pub fn crawl_illusts(crawler: &Crawler, output: PathBuf, illusts: Vec<String>) {
                 let caps = EXT_REGEX.captures(url).unwrap();
                 caps[0].to_string()
             };
+            let image = if embed_metadata {
+                metadata::embed(image, &metadata)
+            } else {
+                image
+            };
             if image_urls.len() == 1 {
                 let mut illust_path = illust_path.clone().into_os_string();
                 illust_path.push(&ext);
@@ -0,0 +1,144 @@
+use crate::crawler::Crawler;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(PartialEq, Eq)]
+pub enum Kind {
+    Image,
+    File,
+    Link,
+}
+
+pub struct Item {
+    pub url: String,
+    pub name: String,
+    pub ext: String,
+    pub kind: Kind,
+}
+
+pub fn save_post(
+    crawler: &Crawler,
+    output: &Path,
+    name: &str,
+    id: &str,
+    items: Vec<Item>,
+    sidecars: Vec<(&str, &str, String)>,
+    metadata: &Value,
+) {
+    // Create a directory unless there is only one image, so that attachments
+    // keep their original names.
+    let output = output.join(name);
+    let flat = items.len() == 1 && items[0].kind == Kind::Image;
+    if !flat {
+        fs::create_dir(&output).unwrap();
+    };
+    let path = |file_name: &str, ext: &str| {
+        if flat {
+            let mut path = output.clone().into_os_string();
+            path.push(ext);
+            PathBuf::from(path)
+        } else {
+            output.join(file_name)
+        }
+    };
+
+    // Save sidecars like the post text, and the metadata as JSON with expected
+    // items, so that they can be verified later.
+    for (file_name, ext, content) in sidecars {
+        if !content.is_empty() {
+            let mut file = File::create(path(file_name, ext)).unwrap();
+            writeln!(file, "{content}").unwrap();
+        }
+    }
+    let item_name = |item: &Item| {
+        if flat {
+            format!("{name}{}", item.ext)
+        } else {
+            format!("{}{}", item.name, item.ext)
+        }
+    };
+    let mut metadata = metadata.clone();
+    metadata["items"] = items
+        .iter()
+        .map(|item| json!({"file": item_name(item), "url": item.url}))
+        .collect();
+    let write_metadata = |metadata: &Value| {
+        let file = File::create(path("post.json", ".json")).unwrap();
+        serde_json::to_writer_pretty(file, metadata).unwrap();
+    };
+    write_metadata(&metadata);
+
+    // Crawl images in this post, which are verified as they are downloaded.
+    let (images, files): (Vec<_>, Vec<_>) = items
+        .iter()
+        .enumerate()
+        .partition(|(_, item)| item.kind == Kind::Image);
+    let image_requests = images
+        .iter()
+        .map(|(_, item)| (item.url.as_str(), Vec::new(), None))
+        .collect();
+    let image_results = crawler.get_image(name, image_requests);
+    for ((i, item), result) in images.iter().zip(image_results) {
+        match result {
+            Ok(bytes) => {
+                let item_path = path(&format!("{}{}", item.name, item.ext), &item.ext);
+                let mut file = File::create(item_path).unwrap();
+                file.write_all(&bytes).unwrap();
+            }
+            Err(err) => println!("Fail to crawl Item {} for Post {id}: {err}", i + 1),
+        }
+    }
+
+    // Stream attachments, which may be much larger than images, into files.
+    // File hosts may return an HTML page, like the virus scan warning of Google
+    // Drive for large files, instead of the file, which is no longer expected.
+    let mut html_pages = Vec::new();
+    for (i, item) in files {
+        let file_name = format!("{}{}", item.name, item.ext);
+        let item_path = path(&file_name, &item.ext);
+        if let Err(err) = crawler.get_file(&file_name, &item.url, &item_path) {
+            println!("Fail to crawl Item {} for Post {id}: {err}", i + 1);
+            let _ = fs::remove_file(&item_path);
+            continue;
+        }
+        let mut head = [0; 512];
+        let len = File::open(&item_path)
+            .and_then(|mut file| file.read(&mut head))
+            .unwrap_or(0);
+        if item.kind == Kind::Link && is_html_page(&head[..len]) {
+            println!("Skip Item {} for Post {id}, which is an HTML page", i + 1);
+            fs::remove_file(&item_path).unwrap();
+            html_pages.push(json!(file_name));
+        }
+    }
+    if !html_pages.is_empty() {
+        metadata["items"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|item| !html_pages.contains(&item["file"]));
+        write_metadata(&metadata);
+    }
+}
+
+fn is_html_page(bytes: &[u8]) -> bool {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(0);
+    let head = String::from_utf8_lossy(&bytes[start..bytes.len().min(start + 15)]).to_lowercase();
+    head.starts_with("<!doctype html") || head.starts_with("<html")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_html_page;
+
+    #[test]
+    fn html_pages() {
+        assert!(is_html_page(b"\n<!DOCTYPE html><html><head>"));
+        assert!(is_html_page(b"<html lang=\"en\">"));
+        assert!(!is_html_page(b"PK\x03\x04"));
+    }
+}
@@ -0,0 +1,535 @@
+use crate::crawler::{self, Crawler, Hash};
+use crate::exhentai;
+use crate::hitomi;
+use crate::nhentai;
+use reqwest::Url;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+const METADATA: &str = "metadata.json";
+const SIDECARS: [&str; 4] = ["post.json", "post.md", "post.html", "links.txt"];
+const REPAIR_DIR: &str = ".repair";
+const IMAGE_EXTS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+
+pub struct Repair {
+    pub crawler: Crawler,
+    pub hitomi: Crawler,
+    pub exhentai: Option<(Crawler, exhentai::Options)>,
+    pub fanbox: Option<Crawler>,
+    pub fantia: Option<Crawler>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Gallery {
+    Exhentai {
+        gid: u64,
+        token: String,
+        range: (usize, usize),
+    },
+    Hitomi {
+        id: String,
+        range: (usize, usize),
+    },
+    Nhentai {
+        id: String,
+        range: (usize, usize),
+    },
+    Booru {
+        posts: Vec<Post>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+struct Post {
+    file: String,
+    md5: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Default)]
+struct Report {
+    missing: usize,
+    corrupted: usize,
+    orphan: usize,
+}
+
+pub fn verify_tree(directory: &Path, repair: Option<&Repair>) {
+    let mut report = Report::default();
+    visit(directory, repair, &mut report);
+    println!(
+        "{} Missing, {} Corrupted, {} Orphan",
+        report.missing, report.corrupted, report.orphan
+    );
+}
+
+fn visit(directory: &Path, repair: Option<&Repair>, report: &mut Report) {
+    let mut entries: Vec<_> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort_unstable();
+    let (directories, files): (Vec<_>, Vec<_>) = entries.into_iter().partition(|p| p.is_dir());
+
+    // Check galleries against their metadata.
+    let gallery = fs::read(directory.join(METADATA))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .and_then(|metadata| extract_gallery(&metadata));
+    match gallery {
+        Some(Gallery::Booru { posts }) => {
+            report_orphans(&files, &posts, &[METADATA], report);
+            verify_posts(directory, &posts, repair, report);
+        }
+        Some(gallery) => verify_pages(directory, &gallery, &files, repair, report),
+        None => {
+            // Check posts against their metadata, which lists their items. A
+            // post with only one image is saved next to its metadata instead
+            // of in its own directory.
+            let mut checked = HashSet::new();
+            for file in &files {
+                let name = file.file_name().unwrap().to_string_lossy();
+                if !name.ends_with(".json") {
+                    continue;
+                }
+                let posts = match fs::read(file)
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                    .and_then(|metadata| extract_items(&metadata))
+                {
+                    Some(posts) => posts,
+                    None => continue,
+                };
+                if name == SIDECARS[0] {
+                    report_orphans(&files, &posts, &SIDECARS, report);
+                }
+                verify_posts(directory, &posts, repair, report);
+                checked.extend(posts.into_iter().map(|post| directory.join(post.file)));
+            }
+
+            // Check any other images.
+            for file in files.iter().filter(|file| !checked.contains(*file)) {
+                let is_image = file
+                    .extension()
+                    .map(|ext| IMAGE_EXTS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+                    .unwrap_or(false);
+                if is_image {
+                    if let Err(err) = crawler::verify_image(&fs::read(file).unwrap()) {
+                        println!("{} - {err}", file.display());
+                        report.corrupted += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    for path in directories {
+        if path.file_name().unwrap() != REPAIR_DIR {
+            visit(&path, repair, report);
+        }
+    }
+}
+
+fn verify_pages(
+    directory: &Path,
+    gallery: &Gallery,
+    files: &[PathBuf],
+    repair: Option<&Repair>,
+    report: &mut Report,
+) {
+    let (start, end) = match gallery {
+        Gallery::Exhentai { range, .. }
+        | Gallery::Hitomi { range, .. }
+        | Gallery::Nhentai { range, .. } => *range,
+        Gallery::Booru { .. } => return,
+    };
+    let count = end - start + 1;
+
+    // Pages are named by their indexes in the downloaded range like `0001.jpg`.
+    let mut pages = BTreeMap::new();
+    for file in files {
+        if file.file_name().unwrap() == METADATA {
+            continue;
+        }
+        match extract_page(file) {
+            Some(pg) if (1..=count).contains(&pg) => {
+                pages.insert(pg, file);
+            }
+            _ => {
+                println!("{} - Orphan file", file.display());
+                report.orphan += 1;
+            }
+        }
+    }
+
+    // Find missing and corrupted pages.
+    let mut missing = Vec::new();
+    let mut bad_pages = Vec::new();
+    for pg in 1..=count {
+        match pages.get(&pg) {
+            Some(file) => {
+                if let Err(err) = crawler::verify_image(&fs::read(file).unwrap()) {
+                    println!("{} - {err}", file.display());
+                    report.corrupted += 1;
+                    bad_pages.push(pg);
+                }
+            }
+            None => {
+                report.missing += 1;
+                missing.push(pg.to_string());
+                bad_pages.push(pg);
+            }
+        }
+    }
+    if !missing.is_empty() {
+        println!(
+            "{} - Missing pages {} of {count}",
+            directory.display(),
+            missing.join(", ")
+        );
+    }
+
+    // Download bad pages again into a temporary directory, in runs of
+    // consecutive pages to save the image limit.
+    let repair = match repair {
+        Some(repair) if !bad_pages.is_empty() => repair,
+        _ => return,
+    };
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &pg in &bad_pages {
+        match runs.last_mut() {
+            Some((_, end)) if *end + 1 == pg => *end = pg,
+            _ => runs.push((pg, pg)),
+        }
+    }
+    let repair_path = directory.join(REPAIR_DIR);
+    let mut repaired = 0;
+    for (first, last) in runs {
+        // Pages of the run are also numbered from 1 in the temporary directory.
+        let run_path = repair_path.join(format!("{first}-{last}"));
+        fs::create_dir_all(&run_path).unwrap();
+        let range = format!("{}-{}", start + first - 1, start + last - 1);
+        match gallery {
+            Gallery::Exhentai { gid, token, .. } => match &repair.exhentai {
+                Some((crawler, options)) => {
+                    let gallery = format!("{gid}/{token}/{range}");
+                    exhentai::crawl_galleries(crawler, run_path.clone(), options, vec![gallery]);
+                }
+                None => {
+                    println!("{} - ExHentai is not configured", directory.display());
+                    break;
+                }
+            },
+            Gallery::Hitomi { id, .. } => {
+                let gallery = format!("{id}/{range}");
+                hitomi::crawl_galleries(&repair.hitomi, run_path.clone(), vec![gallery]);
+            }
+            Gallery::Nhentai { id, .. } => {
+                let gallery = format!("{id}/{range}");
+                nhentai::crawl_galleries(&repair.crawler, run_path.clone(), vec![gallery]);
+            }
+            Gallery::Booru { .. } => unreachable!(),
+        }
+
+        // Replace bad pages only with verified ones, so that corrupted pages are
+        // kept if the repair fails.
+        for entry in fs::read_dir(&run_path).unwrap() {
+            let path = entry.unwrap().path();
+            if !path.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&path).unwrap() {
+                let file = entry.unwrap().path();
+                let pg = match extract_page(&file) {
+                    Some(pg) if pg <= last - first + 1 => pg + first - 1,
+                    _ => continue,
+                };
+                if let Err(err) = crawler::verify_image(&fs::read(&file).unwrap()) {
+                    println!("{} - {err}", file.display());
+                    continue;
+                }
+                let ext = file
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                let page_path = directory.join(format!("{pg:0>4}{ext}"));
+                if let Some(old_path) = pages.get(&pg).filter(|old| **old != &page_path) {
+                    fs::remove_file(old_path).unwrap();
+                }
+                fs::rename(&file, page_path).unwrap();
+                repaired += 1;
+            }
+        }
+    }
+    fs::remove_dir_all(&repair_path).unwrap();
+    println!(
+        "{} - Repaired {repaired} of {} pages",
+        directory.display(),
+        bad_pages.len()
+    );
+}
+
+fn report_orphans(files: &[PathBuf], posts: &[Post], sidecars: &[&str], report: &mut Report) {
+    // Report files which are not listed in the metadata.
+    let names: HashSet<_> = posts.iter().map(|post| post.file.as_str()).collect();
+    for file in files {
+        let name = file.file_name().unwrap().to_string_lossy();
+        if !sidecars.contains(&name.as_ref()) && !names.contains(name.as_ref()) {
+            println!("{} - Orphan file", file.display());
+            report.orphan += 1;
+        }
+    }
+}
+
+fn verify_posts(directory: &Path, posts: &[Post], repair: Option<&Repair>, report: &mut Report) {
+    // Find missing and corrupted posts, which are verified by their MD5 hashes
+    // if they are known.
+    let mut bad_posts = Vec::new();
+    for post in posts {
+        let path = directory.join(&post.file);
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let result = crawler::verify_image(&bytes).and_then(|_| match &post.md5 {
+                    Some(md5) => Hash::Md5(md5.clone()).verify(&bytes),
+                    None => Ok(()),
+                });
+                if let Err(err) = result {
+                    println!("{} - {err}", path.display());
+                    report.corrupted += 1;
+                    bad_posts.push(post);
+                }
+            }
+            Err(_) => {
+                println!("{} - Missing file", path.display());
+                report.missing += 1;
+                bad_posts.push(post);
+            }
+        }
+    }
+
+    // Download bad posts again, with the cookies of their sites.
+    let repair = match repair {
+        Some(repair) if !bad_posts.is_empty() => repair,
+        _ => return,
+    };
+    let mut groups: Vec<(&Crawler, Vec<&Post>)> = Vec::new();
+    for post in bad_posts {
+        let url = match &post.url {
+            Some(url) => url,
+            None => {
+                println!("{} - Unknown url for {}", directory.display(), post.file);
+                continue;
+            }
+        };
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
+        let crawler = if host.ends_with("fanbox.cc") {
+            repair.fanbox.as_ref().ok_or("FANBOX")
+        } else if host.ends_with("fantia.jp") {
+            repair.fantia.as_ref().ok_or("Fantia")
+        } else {
+            Ok(&repair.crawler)
+        };
+        let crawler = match crawler {
+            Ok(crawler) => crawler,
+            Err(site) => {
+                println!("{} - {site} is not configured", directory.display());
+                continue;
+            }
+        };
+        match groups.iter_mut().find(|(c, _)| ptr::eq(*c, crawler)) {
+            Some((_, posts)) => posts.push(post),
+            None => groups.push((crawler, vec![post])),
+        }
+    }
+    for (crawler, posts) in groups {
+        // Images are verified as they are downloaded, while other files like
+        // attachments are streamed.
+        let (images, files): (Vec<_>, Vec<_>) = posts.into_iter().partition(|post| {
+            post.md5.is_some()
+                || Path::new(&post.file)
+                    .extension()
+                    .map(|ext| IMAGE_EXTS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+                    .unwrap_or(false)
+        });
+        let image_requests = images
+            .iter()
+            .map(|post| {
+                let url = post.url.as_ref().unwrap();
+                (url.as_str(), Vec::new(), post.md5.clone().map(Hash::Md5))
+            })
+            .collect();
+        let image_results = crawler.get_image("", image_requests);
+        for (post, result) in images.iter().zip(image_results) {
+            match result {
+                Ok(image) => {
+                    let mut file = File::create(directory.join(&post.file)).unwrap();
+                    file.write_all(&image).unwrap();
+                    println!("{} - Repaired {}", directory.display(), post.file);
+                }
+                Err(err) => println!("Fail to repair {}: {err}", post.file),
+            }
+        }
+        for post in files {
+            let path = directory.join(&post.file);
+            match crawler.get_file(&post.file, post.url.as_ref().unwrap(), &path) {
+                Ok(_) => println!("{} - Repaired {}", directory.display(), post.file),
+                Err(err) => {
+                    println!("Fail to repair {}: {err}", post.file);
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+    }
+}
+
+fn extract_gallery(metadata: &Value) -> Option<Gallery> {
+    if let Some(posts) = metadata.as_array() {
+        // Booru metadata lists posts.
+        let posts = posts
+            .iter()
+            .map(|post| {
+                Some(Post {
+                    file: post["file"].as_str()?.to_string(),
+                    md5: Some(post["md5"].as_str()?.to_string()),
+                    url: post["url"].as_str().map(String::from),
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Gallery::Booru { posts })
+    } else if let Some(gid) = metadata["gid"].as_u64() {
+        // ExHentai metadata comes from the API, where the count is a string.
+        let count = metadata["filecount"].as_str()?.parse().ok()?;
+        Some(Gallery::Exhentai {
+            gid,
+            token: metadata["token"].as_str()?.to_string(),
+            range: extract_range(metadata).unwrap_or((1, count)),
+        })
+    } else if metadata.get("media_id").is_some() {
+        // nhentai metadata is the gallery info from the API.
+        let count = metadata["images"]["pages"].as_array()?.len();
+        Some(Gallery::Nhentai {
+            id: metadata["id"].as_u64()?.to_string(),
+            range: extract_range(metadata).unwrap_or((1, count)),
+        })
+    } else {
+        // Hitomi metadata is the gallery info.
+        let id = match &metadata["id"] {
+            Value::String(id) => id.clone(),
+            Value::Number(id) => id.to_string(),
+            _ => return None,
+        };
+        let count = metadata["files"].as_array()?.len();
+        Some(Gallery::Hitomi {
+            id,
+            range: extract_range(metadata).unwrap_or((1, count)),
+        })
+    }
+}
+
+fn extract_range(metadata: &Value) -> Option<(usize, usize)> {
+    // Galleries downloaded with a range record it in their metadata.
+    let range = metadata["range"].as_array()?;
+    match range[..] {
+        [ref start, ref end] => Some((start.as_u64()? as usize, end.as_u64()? as usize)),
+        _ => None,
+    }
+}
+
+fn extract_items(metadata: &Value) -> Option<Vec<Post>> {
+    // Post metadata lists items with their urls.
+    metadata["items"]
+        .as_array()?
+        .iter()
+        .map(|item| {
+            Some(Post {
+                file: item["file"].as_str()?.to_string(),
+                md5: None,
+                url: item["url"].as_str().map(String::from),
+            })
+        })
+        .collect()
+}
+
+fn extract_page(file: &Path) -> Option<usize> {
+    let stem = file.file_stem()?.to_str()?;
+    if stem.len() == 4 {
+        stem.parse().ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_gallery, extract_items, extract_page, Gallery, Post};
+    use serde_json::json;
+    use std::path::Path;
+
+    #[test]
+    fn galleries() {
+        let exhentai = json!({"gid": 1, "token": "abcdef0123", "filecount": "20"});
+        assert_eq!(
+            extract_gallery(&exhentai),
+            Some(Gallery::Exhentai {
+                gid: 1,
+                token: String::from("abcdef0123"),
+                range: (1, 20),
+            })
+        );
+        let hitomi = json!({"id": "2", "files": [{"hash": "a"}, {"hash": "b"}], "range": [2, 2]});
+        assert_eq!(
+            extract_gallery(&hitomi),
+            Some(Gallery::Hitomi {
+                id: String::from("2"),
+                range: (2, 2),
+            })
+        );
+        let nhentai = json!({"id": 5, "media_id": "9", "images": {"pages": [{"t": "j"}]}});
+        assert_eq!(
+            extract_gallery(&nhentai),
+            Some(Gallery::Nhentai {
+                id: String::from("5"),
+                range: (1, 1),
+            })
+        );
+        let booru = json!([{"id": 3, "file": "3_abc.png", "md5": "abc"}]);
+        assert_eq!(
+            extract_gallery(&booru),
+            Some(Gallery::Booru {
+                posts: vec![Post {
+                    file: String::from("3_abc.png"),
+                    md5: Some(String::from("abc")),
+                    url: None,
+                }],
+            })
+        );
+        assert_eq!(extract_gallery(&json!({"id": 4, "title": "post"})), None);
+    }
+
+    #[test]
+    fn items() {
+        let post = json!({"id": "6", "items": [{"file": "a.png", "url": "https://a/a.png"}]});
+        assert_eq!(
+            extract_items(&post),
+            Some(vec![Post {
+                file: String::from("a.png"),
+                md5: None,
+                url: Some(String::from("https://a/a.png")),
+            }])
+        );
+        assert_eq!(extract_items(&json!({"id": "7"})), None);
+    }
+
+    #[test]
+    fn pages() {
+        assert_eq!(extract_page(Path::new("a/0007.jpg")), Some(7));
+        assert_eq!(extract_page(Path::new("a/12345_p0.jpg")), None);
+        assert_eq!(extract_page(Path::new("a/cover.jpg")), None);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::crawler::Crawler;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+pub const BEARER_TOKEN: &str = "Bearer AAAAAAAAAAAAAAAAAAAAANRILgAAAAAAnNwIzUejRCOuH5E6I8xnZz4puTs%3D1Zv7ttfk8LF81IUq16cHjhLTvJu4FA33AGWWjCpTnA";
+const USER_BY_SCREEN_NAME_URL: &str =
+    "https://x.com/i/api/graphql/G3KGOASz96M-Qu0nwmGXNg/UserByScreenName";
+const USER_MEDIA_URL: &str = "https://x.com/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia";
+const FEATURES: &str = r#"{"responsive_web_graphql_exclude_directive_enabled":true,"verified_phone_label_enabled":false,"creator_subscriptions_tweet_preview_api_enabled":true,"responsive_web_graphql_timeline_navigation_enabled":true,"responsive_web_graphql_skip_user_profile_image_extensions_enabled":false,"hidden_profile_likes_enabled":true,"hidden_profile_subscriptions_enabled":true,"highlights_tweets_tab_ui_enabled":true,"subscriptions_verification_info_is_identity_verified_enabled":true,"subscriptions_verification_info_verified_since_enabled":true,"c9s_tweet_anatomy_moderator_badge_enabled":true,"tweetypie_unmention_optimization_enabled":true,"responsive_web_edit_tweet_api_enabled":true,"graphql_is_translatable_rweb_tweet_is_translatable_enabled":true,"view_counts_everywhere_api_enabled":true,"longform_notetweets_consumption_enabled":true,"responsive_web_twitter_article_tweet_consumption_enabled":false,"tweet_awards_web_tipping_enabled":false,"freedom_of_speech_not_reach_fetch_enabled":true,"standardized_nudges_misinfo":true,"tweet_with_visibility_results_prefer_gql_limited_actions_policy_enabled":true,"rweb_video_timestamps_enabled":true,"longform_notetweets_rich_text_read_enabled":true,"longform_notetweets_inline_media_enabled":true,"responsive_web_media_download_video_enabled":false,"responsive_web_enhance_cards_enabled":false}"#;
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+lazy_static! {
+    static ref DATE_REGEX: Regex =
+        Regex::new(r"([A-Z][a-z]{2}) ([0-9]{2}) .* [0-9]{2}([0-9]{2})$").unwrap();
+}
+
+struct Tweet {
+    id: String,
+    date: String,
+    media: Vec<(String, String)>,
+}
+
+pub fn crawl_users(crawler: &Crawler, output: PathBuf, users: Vec<String>) {
+    for user in users {
+        // Resolve the user id from the handle.
+        let variables = json!({ "screen_name": user, "withSafetyModeUserFields": true });
+        let user_result = crawler
+            .get_json(
+                "",
+                vec![(
+                    USER_BY_SCREEN_NAME_URL,
+                    vec![
+                        ("variables", &variables.to_string()),
+                        ("features", FEATURES),
+                    ],
+                )],
+            )
+            .pop()
+            .unwrap();
+        let (user_id, name) = match user_result {
+            Ok(json) => {
+                let result = &json["data"]["user"]["result"];
+                match (
+                    result["rest_id"].as_str(),
+                    result["legacy"]["name"].as_str(),
+                ) {
+                    (Some(user_id), Some(name)) => (user_id.to_string(), name.to_string()),
+                    _ => {
+                        println!("User {user} is not found");
+                        continue;
+                    }
+                }
+            }
+            Err(err) => {
+                println!("Fail to crawl the profile for User {user}: {err}");
+                continue;
+            }
+        };
+
+        // Walk the media timeline until no more tweets are returned.
+        let mut tweets: Vec<Tweet> = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut variables = json!({
+                "userId": user_id,
+                "count": 100,
+                "includePromotedContent": false,
+                "withClientEventToken": false,
+                "withBirdwatchNotes": false,
+                "withVoice": true,
+                "withV2Timeline": true,
+            });
+            if let Some(cursor) = &cursor {
+                variables["cursor"] = json!(cursor);
+            }
+            let timeline_result = crawler
+                .get_json(
+                    "",
+                    vec![(
+                        USER_MEDIA_URL,
+                        vec![
+                            ("variables", &variables.to_string()),
+                            ("features", FEATURES),
+                        ],
+                    )],
+                )
+                .pop()
+                .unwrap();
+            let timeline = match timeline_result {
+                Ok(json) => json,
+                Err(err) => {
+                    println!("Fail to crawl the media timeline for User {user}: {err}");
+                    break;
+                }
+            };
+            let (page_tweets, next_cursor) = extract_timeline(&timeline);
+            let total = tweets.len();
+            for tweet in page_tweets {
+                if tweets.iter().all(|t| t.id != tweet.id) {
+                    tweets.push(tweet);
+                }
+            }
+            if tweets.len() == total || next_cursor.is_none() {
+                break;
+            }
+            cursor = next_cursor;
+        }
+
+        // Create the user directory.
+        let mut directory_path = output.clone();
+        let directory = sanitize_filename::sanitize(format!("[{name}]"));
+        directory_path.push(&directory);
+        fs::create_dir(&directory_path).unwrap();
+        println!("{name} - {} Tweets", tweets.len());
+
+        // Crawl media of tweets.
+        let items: Vec<_> = tweets
+            .iter()
+            .flat_map(|tweet| {
+                tweet.media.iter().enumerate().map(|(i, (url, ext))| {
+                    let n = i + 1;
+                    let file_name = format!("[{name}] [{}] {}_{n}{ext}", tweet.date, tweet.id);
+                    (url.as_str(), sanitize_filename::sanitize(file_name))
+                })
+            })
+            .collect();
+        let (videos, images): (Vec<_>, Vec<_>) = items
+            .iter()
+            .partition(|(_, file_name)| file_name.ends_with(".mp4"));
+
+        // Crawl images in chunks, writing each chunk before the next one so
+        // that long timelines are not held in memory.
+        for chunk in images.chunks(100) {
+            let image_requests = chunk
+                .iter()
+                .map(|(url, _)| (*url, Vec::new(), None))
+                .collect();
+            let image_results = crawler.get_image(&directory, image_requests);
+            for ((_, file_name), image) in chunk.iter().zip(image_results) {
+                let image = match image {
+                    Ok(image) => image,
+                    Err(err) => {
+                        println!("Fail to crawl {file_name}: {err}");
+                        continue;
+                    }
+                };
+                let mut file = File::create(directory_path.join(file_name)).unwrap();
+                file.write_all(&image).unwrap();
+            }
+        }
+
+        // Stream videos, which may be much larger than images, into files.
+        for (url, file_name) in videos {
+            let path = directory_path.join(file_name);
+            if let Err(err) = crawler.get_file(file_name, url, &path) {
+                println!("Fail to crawl {file_name}: {err}");
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+fn extract_timeline(timeline: &Value) -> (Vec<Tweet>, Option<String>) {
+    // Entries are nested differently in the first and later pages, so tweets
+    // and the bottom cursor are searched in the whole response.
+    let mut tweets = Vec::new();
+    let mut cursor = None;
+    let mut stack = vec![timeline];
+    while let Some(value) = stack.pop() {
+        match value {
+            Value::Object(map) => {
+                if let Some(result) = map.get("tweet_results").map(|r| &r["result"]) {
+                    // Some tweets are wrapped with their visibility results.
+                    let tweet = result.get("tweet").unwrap_or(result);
+                    tweets.extend(extract_tweet(tweet));
+                    continue;
+                }
+                if map.get("cursorType").and_then(|t| t.as_str()) == Some("Bottom") {
+                    cursor = map.get("value").and_then(|v| v.as_str()).map(String::from);
+                    continue;
+                }
+                stack.extend(map.values().rev());
+            }
+            Value::Array(values) => stack.extend(values.iter().rev()),
+            _ => (),
+        }
+    }
+    (tweets, cursor)
+}
+
+fn extract_tweet(tweet: &Value) -> Option<Tweet> {
+    let id = tweet["rest_id"].as_str()?.to_string();
+    let legacy = &tweet["legacy"];
+    let date = convert_date(legacy["created_at"].as_str()?)?;
+    let media = legacy["extended_entities"]["media"]
+        .as_array()?
+        .iter()
+        .filter_map(|media| match media["type"].as_str()? {
+            "photo" => {
+                // Request the original size of images.
+                let url = media["media_url_https"].as_str()?;
+                let dot = url.rfind('.')?;
+                let ext = &url[dot + 1..];
+                Some((
+                    format!("{}?format={ext}&name=orig", &url[..dot]),
+                    format!(".{ext}"),
+                ))
+            }
+            _ => {
+                // Videos and GIFs are downloaded at the highest bitrate.
+                let variant = media["video_info"]["variants"]
+                    .as_array()?
+                    .iter()
+                    .filter(|variant| variant["content_type"].as_str() == Some("video/mp4"))
+                    .max_by_key(|variant| variant["bitrate"].as_u64().unwrap_or(0))?;
+                let url = variant["url"].as_str()?;
+                Some((url.to_string(), String::from(".mp4")))
+            }
+        })
+        .collect();
+    Some(Tweet { id, date, media })
+}
+
+fn convert_date(date: &str) -> Option<String> {
+    // Dates look like `Sat Jan 06 10:00:00 +0000 2024`.
+    let caps = DATE_REGEX.captures(date)?;
+    let month = MONTHS.iter().position(|month| *month == &caps[1])? + 1;
+    Some(format!("{}{month:0>2}{}", &caps[3], &caps[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_date, extract_timeline};
+    use serde_json::json;
+
+    #[test]
+    fn date() {
+        assert_eq!(
+            convert_date("Sat Jan 06 10:00:00 +0000 2024").unwrap(),
+            "240106"
+        );
+    }
+
+    #[test]
+    fn timeline() {
+        let tweet = json!({
+            "rest_id": "100",
+            "legacy": {
+                "created_at": "Sat Jan 06 10:00:00 +0000 2024",
+                "extended_entities": {"media": [
+                    {"type": "photo", "media_url_https": "https://pbs.twimg.com/media/A.jpg"},
+                    {"type": "video", "video_info": {"variants": [
+                        {"content_type": "application/x-mpegURL", "url": "https://video.twimg.com/a.m3u8"},
+                        {"content_type": "video/mp4", "bitrate": 832000, "url": "https://video.twimg.com/low.mp4"},
+                        {"content_type": "video/mp4", "bitrate": 2176000, "url": "https://video.twimg.com/high.mp4"},
+                    ]}},
+                ]},
+            },
+        });
+        let timeline = json!({"data": {"user": {"result": {"timeline_v2": {"timeline": {
+            "instructions": [{"type": "TimelineAddEntries", "entries": [
+                {"content": {"items": [{"item": {"itemContent": {
+                    "tweet_results": {"result": {"__typename": "TweetWithVisibilityResults", "tweet": tweet}},
+                }}}]}},
+                {"content": {"cursorType": "Top", "value": "top"}},
+                {"content": {"cursorType": "Bottom", "value": "bottom"}},
+            ]}],
+        }}}}}});
+        let (tweets, cursor) = extract_timeline(&timeline);
+        assert_eq!(cursor.unwrap(), "bottom");
+        assert_eq!(tweets.len(), 1);
+        assert_eq!(tweets[0].id, "100");
+        assert_eq!(tweets[0].date, "240106");
+        assert_eq!(
+            tweets[0].media,
+            vec![
+                (
+                    String::from("https://pbs.twimg.com/media/A?format=jpg&name=orig"),
+                    String::from(".jpg")
+                ),
+                (
+                    String::from("https://video.twimg.com/high.mp4"),
+                    String::from(".mp4")
+                ),
+            ]
+        );
+    }
+}